@@ -0,0 +1,169 @@
+// Benchmarks for the emulator core: decoding, executing each class of
+// instruction on both backends, drawing sprites and running whole frames of
+// small programs. Run with `cargo bench`.
+//
+// The programs are assembled here rather than read from tests/roms, so every
+// machine benchmarks the same code.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+
+use rustychips::emulator::Emulator;
+use rustychips::emulator::config::{Backend, Config};
+use rustychips::emulator::display::Display;
+
+// Enough instructions per frame that the per-frame work doesn't dominate.
+const INSTRUCTIONS_PER_FRAME: u32 = 1000;
+
+fn decode(c: &mut Criterion) {
+    let words: Vec<u16> = (0..=u16::MAX).collect();
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(words.len() as u64));
+    group.bench_function("every word", |b| b.iter(|| {
+        for word in &words {
+            black_box(Emulator::decode(black_box(*word)));
+        }
+    }));
+    group.finish();
+}
+
+// Fills a loop at 0x200 with copies of `body`, with a subroutine at 0x300
+// that only returns for calls to go to.
+fn loop_rom(body: &[u16]) -> Vec<u8> {
+    let mut code: Vec<u16> = body.iter().copied().cycle().take(126 / body.len() * body.len()).collect();
+    code.push(0x1200);
+    let mut rom: Vec<u8> = code.iter().flat_map(|word| word.to_be_bytes()).collect();
+    rom.resize(0x100, 0);
+    rom.extend_from_slice(&[0x00, 0xee]);
+    rom
+}
+
+fn execute(c: &mut Criterion) {
+    let classes: [(&str, &[u16]); 8] = [
+        ("load and add", &[0x6012, 0x7103, 0x62ff, 0x7201]),
+        ("logic and arithmetic", &[0x8010, 0x8121, 0x8232, 0x8343, 0x8454, 0x8566, 0x8677, 0x878e]),
+        ("index and font", &[0xa300, 0xf01e, 0xf129, 0xf230]),
+        ("skips", &[0x3001, 0x4000, 0x5010, 0x9000]),
+        ("calls", &[0x2300]),
+        ("memory", &[0xa800, 0xf033, 0xf355, 0xf365]),
+        ("timers", &[0xf015, 0xf118, 0xf207]),
+        ("draw", &[0xa050, 0xd125, 0x7103, 0x7201]),
+    ];
+    for (class, body) in classes {
+        let rom = loop_rom(body);
+        let mut group = c.benchmark_group(format!("execute/{}", class));
+        group.throughput(Throughput::Elements(INSTRUCTIONS_PER_FRAME as u64));
+        for (name, backend) in [("interpreter", Backend::Interpreter), ("recompiler", Backend::Recompiler)] {
+            let mut emu = Emulator::with_config(Config { backend, ..Config::default() }).unwrap();
+            emu.load(rom.clone()).unwrap();
+            emu.set_instructions_per_frame(INSTRUCTIONS_PER_FRAME);
+            group.bench_function(name, |b| b.iter(|| emu.run_frames(1).unwrap()));
+        }
+        group.finish();
+    }
+}
+
+fn draw_sprite(c: &mut Criterion) {
+    let sprite = [0xf0, 0x90, 0xf0, 0x90, 0xf0, 0xff, 0x81, 0x42, 0x24, 0x18, 0x18, 0x24, 0x42, 0x81, 0xff];
+    let mut group = c.benchmark_group("draw sprite");
+    for (name, x, y, wrap) in [
+        ("aligned", 8, 4, false),
+        ("unaligned", 13, 4, false),
+        ("clipped", 60, 25, false),
+        ("wrapped", 60, 25, true),
+    ] {
+        let mut display = Display::new(wrap);
+        group.bench_function(name, |b| b.iter(|| {
+            display.write_sprite(black_box(x), black_box(y), black_box(&sprite))
+        }));
+    }
+    group.finish();
+}
+
+// Small programs in the style of real ones, assembled at 0x200.
+fn programs() -> Vec<(&'static str, Vec<u8>)> {
+    let assemble = |code: &[u16]| code.iter().flat_map(|word| word.to_be_bytes()).collect();
+    vec![
+        // Moves a digit across the screen, erasing it at the old position.
+        ("bouncing digit", assemble(&[
+            0x00e0, // CLS
+            0x6000, // LD V0, 0
+            0x6108, // LD V1, 8
+            0x6205, // LD V2, 5
+            0xf229, // LD F, V2
+            0xd015, // DRW V0, V1, 5
+            0x6301, // LD V3, 1
+            0xf315, // LD DT, V3
+            0xf407, // LD V4, DT
+            0x3400, // SE V4, 0
+            0x1210, // JP 210
+            0xd015, // DRW V0, V1, 5
+            0x7001, // ADD V0, 1
+            0x120a, // JP 20A
+        ])),
+        // Counts up and shows the count in decimal, like a score.
+        ("score counter", assemble(&[
+            0x00e0, // CLS
+            0xa300, // LD I, 300
+            0xf533, // LD B, V5
+            0xf265, // LD V2, [I]
+            0x6a00, // LD VA, 0
+            0x6b00, // LD VB, 0
+            0xf029, // LD F, V0
+            0xdab5, // DRW VA, VB, 5
+            0x7a05, // ADD VA, 5
+            0xf129, // LD F, V1
+            0xdab5, // DRW VA, VB, 5
+            0x7a05, // ADD VA, 5
+            0xf229, // LD F, V2
+            0xdab5, // DRW VA, VB, 5
+            0x7501, // ADD V5, 1
+            0x1200, // JP 200
+        ])),
+        // Fills the screen with tall sprites, then erases them again.
+        ("sprite field", assemble(&[
+            0x00e0, // CLS
+            0xa21a, // LD I, 21A
+            0x6a00, // LD VA, 0
+            0x6b00, // LD VB, 0
+            0xdabf, // DRW VA, VB, 15
+            0x7a08, // ADD VA, 8
+            0x3a40, // SE VA, 64
+            0x1208, // JP 208
+            0x6a00, // LD VA, 0
+            0x7b10, // ADD VB, 16
+            0x3b20, // SE VB, 32
+            0x1208, // JP 208
+            0x1204, // JP 204
+            0xff81, 0x4224, 0x1818, 0x2442, 0x81ff, 0x8142, 0x2418, 0x1800, // sprite at 21A
+        ])),
+    ]
+}
+
+// One emulated second of each program, from a fresh start every time.
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(60));
+    for (name, rom) in programs() {
+        for (backend_name, backend) in [("interpreter", Backend::Interpreter), ("recompiler", Backend::Recompiler)] {
+            let setup = || {
+                let mut emu = Emulator::with_config(Config { backend, ..Config::default() }).unwrap();
+                emu.load(rom.clone()).unwrap();
+                emu
+            };
+            group.bench_function(BenchmarkId::new(backend_name, name), |b| b.iter_batched(
+                setup,
+                |mut emu| {
+                    emu.run_frames(60).unwrap();
+                    emu
+                },
+                BatchSize::SmallInput,
+            ));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, execute, draw_sprite, frames);
+criterion_main!(benches);
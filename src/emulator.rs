@@ -1,15 +1,60 @@
-mod display;
+pub mod config;
+pub mod display;
+pub mod error;
+pub mod font;
+mod frame;
+mod hotkey;
 mod keypad;
+mod keypad_view;
+pub mod memory;
+mod memory_view;
+pub mod profiler;
+mod recompiler;
+mod renderer;
+pub mod rom;
+pub mod sprite_view;
+pub mod stack;
+mod status_view;
+pub mod tracer;
+#[cfg(test)]
+mod tests;
 
+use crate::emulator::frame::{Frame, FrameBuffer};
+use crate::emulator::hotkey::Hotkey;
 use crate::emulator::keypad::Keypad;
+use crate::emulator::config::{Backend, Config, IndexIncrement, IndexOverflow, UnknownOpcodePolicy};
 use crate::emulator::display::Display;
+use crate::emulator::error::Error;
+use crate::emulator::font::{check_font_file, BIG_FONT_SIZE, SCHIP_BIG, SMALL_FONT_SIZE};
+use crate::emulator::memory::{Hook, Memory, XOCHIP_MEMORY_SIZE};
+use crate::emulator::memory_view::MemoryView;
+use crate::emulator::profiler::Profiler;
+use crate::emulator::recompiler::{Block, BlockCache};
+use crate::emulator::renderer::Terminal;
+use crate::emulator::rom::Rom;
+use crate::emulator::stack::{CallFrame, Stack};
+use crate::emulator::status_view::{FpsCounter, Status};
+use crate::emulator::tracer::{Tracer, TraceEntry};
 use rand::Rng;
-use std::time::SystemTime;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
-enum Opcode {
+// Fast-forward and slow motion go up to 8x either way.
+const MAX_SPEED: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opcode {
+    Sys(u16),                       // 0nnn
     ClearScreen,                    // 00E0
     Return,                         // 00EE
+    Exit,                           // 00FD (SCHIP)
     Jump(u16),                      // 1nnn
     Call(u16),                      // 2nnn
     Skip(u16,u8),                   // 3xkk
@@ -41,240 +86,520 @@ enum Opcode {
     SetSoundFromReg(u16),           // Fx18
     AddI(u16),                      // Fx1E
     SetIToFontDigit(u16),           // Fx29
+    SetIToBigFontDigit(u16),        // Fx30
     BinaryCodeI(u16),               // Fx33
     CopyRegistersToI(u16),          // Fx55
     CopyIToRegisters(u16),          // Fx65
+    Unknown(u16),
 }    
 
+impl Opcode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opcode::Sys(..) => "Sys",
+            Opcode::ClearScreen => "ClearScreen",
+            Opcode::Return => "Return",
+            Opcode::Exit => "Exit",
+            Opcode::Jump(..) => "Jump",
+            Opcode::Call(..) => "Call",
+            Opcode::Skip(..) => "Skip",
+            Opcode::SkipNotEqual(..) => "SkipNotEqual",
+            Opcode::SkipRegEqual(..) => "SkipRegEqual",
+            Opcode::Set(..) => "Set",
+            Opcode::IncrementReg(..) => "IncrementReg",
+            Opcode::CopyReg(..) => "CopyReg",
+            Opcode::BitwiseOr(..) => "BitwiseOr",
+            Opcode::BitwiseAnd(..) => "BitwiseAnd",
+            Opcode::BitwiseXor(..) => "BitwiseXor",
+            Opcode::AddReg(..) => "AddReg",
+            Opcode::SubtractReg(..) => "SubtractReg",
+            Opcode::BitwiseRight(..) => "BitwiseRight",
+            Opcode::NegativeSubtractReg(..) => "NegativeSubtractReg",
+            Opcode::BitwiseLeft(..) => "BitwiseLeft",
+            Opcode::SkipRegNotEqual(..) => "SkipRegNotEqual",
+            Opcode::SetI(..) => "SetI",
+            Opcode::JumpOffset(..) => "JumpOffset",
+            Opcode::RandomAnd(..) => "RandomAnd",
+            Opcode::Draw(..) => "Draw",
+            Opcode::SkipKeyPressed(..) => "SkipKeyPressed",
+            Opcode::SkipKeyNotPressed(..) => "SkipKeyNotPressed",
+            Opcode::CopyDelayToReg(..) => "CopyDelayToReg",
+            Opcode::WaitForKeyPress(..) => "WaitForKeyPress",
+            Opcode::SetDelayFromReg(..) => "SetDelayFromReg",
+            Opcode::SetSoundFromReg(..) => "SetSoundFromReg",
+            Opcode::AddI(..) => "AddI",
+            Opcode::SetIToFontDigit(..) => "SetIToFontDigit",
+            Opcode::SetIToBigFontDigit(..) => "SetIToBigFontDigit",
+            Opcode::BinaryCodeI(..) => "BinaryCodeI",
+            Opcode::CopyRegistersToI(..) => "CopyRegistersToI",
+            Opcode::CopyIToRegisters(..) => "CopyIToRegisters",
+            Opcode::Unknown(..) => "Unknown",
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::Sys(addr) => write!(f, "SYS {:03X}", addr),
+            Opcode::ClearScreen => write!(f, "CLS"),
+            Opcode::Return => write!(f, "RET"),
+            Opcode::Exit => write!(f, "EXIT"),
+            Opcode::Jump(addr) => write!(f, "JP {:03X}", addr),
+            Opcode::Call(addr) => write!(f, "CALL {:03X}", addr),
+            Opcode::Skip(reg, val) => write!(f, "SE V{:X}, {:02X}", reg, val),
+            Opcode::SkipNotEqual(reg, val) => write!(f, "SNE V{:X}, {:02X}", reg, val),
+            Opcode::SkipRegEqual(reg1, reg2) => write!(f, "SE V{:X}, V{:X}", reg1, reg2),
+            Opcode::Set(reg, val) => write!(f, "LD V{:X}, {:02X}", reg, val),
+            Opcode::IncrementReg(reg, val) => write!(f, "ADD V{:X}, {:02X}", reg, val),
+            Opcode::CopyReg(reg1, reg2) => write!(f, "LD V{:X}, V{:X}", reg1, reg2),
+            Opcode::BitwiseOr(reg1, reg2) => write!(f, "OR V{:X}, V{:X}", reg1, reg2),
+            Opcode::BitwiseAnd(reg1, reg2) => write!(f, "AND V{:X}, V{:X}", reg1, reg2),
+            Opcode::BitwiseXor(reg1, reg2) => write!(f, "XOR V{:X}, V{:X}", reg1, reg2),
+            Opcode::AddReg(reg1, reg2) => write!(f, "ADD V{:X}, V{:X}", reg1, reg2),
+            Opcode::SubtractReg(reg1, reg2) => write!(f, "SUB V{:X}, V{:X}", reg1, reg2),
+            Opcode::BitwiseRight(reg1, reg2) => write!(f, "SHR V{:X}, V{:X}", reg1, reg2),
+            Opcode::NegativeSubtractReg(reg1, reg2) => write!(f, "SUBN V{:X}, V{:X}", reg1, reg2),
+            Opcode::BitwiseLeft(reg1, reg2) => write!(f, "SHL V{:X}, V{:X}", reg1, reg2),
+            Opcode::SkipRegNotEqual(reg1, reg2) => write!(f, "SNE V{:X}, V{:X}", reg1, reg2),
+            Opcode::SetI(addr) => write!(f, "LD I, {:03X}", addr),
+            Opcode::JumpOffset(addr) => write!(f, "JP V0, {:03X}", addr),
+            Opcode::RandomAnd(reg, val) => write!(f, "RND V{:X}, {:02X}", reg, val),
+            Opcode::Draw(x, y, rows) => write!(f, "DRW V{:X}, V{:X}, {:X}", x, y, rows),
+            Opcode::SkipKeyPressed(reg) => write!(f, "SKP V{:X}", reg),
+            Opcode::SkipKeyNotPressed(reg) => write!(f, "SKNP V{:X}", reg),
+            Opcode::CopyDelayToReg(reg) => write!(f, "LD V{:X}, DT", reg),
+            Opcode::WaitForKeyPress(reg) => write!(f, "LD V{:X}, K", reg),
+            Opcode::SetDelayFromReg(reg) => write!(f, "LD DT, V{:X}", reg),
+            Opcode::SetSoundFromReg(reg) => write!(f, "LD ST, V{:X}", reg),
+            Opcode::AddI(reg) => write!(f, "ADD I, V{:X}", reg),
+            Opcode::SetIToFontDigit(reg) => write!(f, "LD F, V{:X}", reg),
+            Opcode::SetIToBigFontDigit(reg) => write!(f, "LD HF, V{:X}", reg),
+            Opcode::BinaryCodeI(reg) => write!(f, "LD B, V{:X}", reg),
+            Opcode::CopyRegistersToI(reg) => write!(f, "LD [I], V{:X}", reg),
+            Opcode::CopyIToRegisters(reg) => write!(f, "LD V{:X}, [I]", reg),
+            Opcode::Unknown(word) => write!(f, "DW {:04X}", word),
+        }
+    }
+}
+
+// Why a program stopped running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Halt {
+    // SCHIP 00FD.
+    Exit,
+    // A 1nnn jump to itself, which nothing can break out of.
+    InfiniteLoop(u16),
+    // The PC ran past the last instruction that fits in memory.
+    EndOfMemory,
+    // An unknown opcode with the halt policy.
+    UnknownOpcode { addr: u16, opcode: u16 },
+    // The user quit from the terminal.
+    Quit,
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Halt::Exit => write!(f, "program exited"),
+            Halt::InfiniteLoop(addr) => write!(f, "infinite loop at {:03X}", addr),
+            Halt::EndOfMemory => write!(f, "ran off the end of memory"),
+            Halt::UnknownOpcode { addr, opcode } => write!(f, "unknown opcode {:04X} at {:03X}", opcode, addr),
+            Halt::Quit => write!(f, "quit"),
+        }
+    }
+}
+
 pub struct Emulator {
+    config: Config,
     v: [u8; 16],
-    memory: Vec<u8>,
+    memory: Memory,
     pc: u16,
     i: u16,
-    stack: Vec<u16>,
-    sp: u16,
+    stack: Stack,
     display: Display,
     sound_timer: u8,
     delay_timer: u8,
     draw_flag: bool,
+    // The keypad as last drawn, so that key presses show up straight away.
+    drawn_keys: [bool; 16],
+    // The delay and sound timers as last drawn, so the status panel keeps
+    // counting them down while the display doesn't change.
+    drawn_timers: (u8, u8),
+    // Set by Dxyn with the display wait quirk to end the frame early.
+    waiting_for_vblank: bool,
+    // The height of the last sprite drawn, for the sprite panel.
+    last_draw_rows: Option<u8>,
     frequency: u8,
-    executed: Vec<Opcode>
+    fps: FpsCounter,
+    // Emulated frames run at 2^speed times real time.
+    speed: i32,
+    instructions_per_frame: u32,
+    cycle: u64,
+    tracer: Tracer,
+    profiler: Option<Profiler>,
+    blocks: BlockCache,
+    keypad: Keypad,
+    memory_view: MemoryView,
+    recent_writes: VecDeque<u16>,
+    paused: bool,
+    quit: bool,
+    // What was loaded, kept for resets.
+    rom: Option<Rom>,
+    rom_name: String,
+    font: Vec<u8>,
+    // Where the save hotkey writes a memory image.
+    save_path: Option<String>,
+    // Feedback from the last hotkey, shown under the registers.
+    message: Option<String>,
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Emulator::new()
+    }
 }
 
 impl Emulator {
     pub fn new() -> Self {
+        Emulator::with_config(Config::default()).expect("The default configuration is valid")
+    }
+
+    // Fails if the memory is bigger than 16 bit addresses can reach, or too
+    // small for the font.
+    pub fn with_config(config: Config) -> Result<Self, Error> {
+        if config.memory_size > XOCHIP_MEMORY_SIZE {
+            return Err(Error::MemoryTooLarge { size: config.memory_size });
+        }
+        let font = [&config.font.small()[..], &SCHIP_BIG[..]].concat();
+        if config.font_address as usize + font.len() > config.memory_size {
+            return Err(Error::FontDoesNotFit { addr: config.font_address, memory_size: config.memory_size });
+        }
 
         let mut emulator = Emulator {
+            config,
             v: [0; 16],
-            memory: vec![0; 4096],
+            memory: Memory::new(config.memory_size, config.protect_interpreter),
             pc: 0,
             i: 0,
-            stack: Vec::new(),
-            sp: 0,
-            display: Display::new(),
+            stack: Stack::new(config.stack_size),
+            display: Display::new(config.quirks.wrap_sprites),
             sound_timer: 0,
             delay_timer: 0,
             draw_flag: false,
+            drawn_keys: [false; 16],
+            drawn_timers: (0, 0),
+            waiting_for_vblank: false,
+            last_draw_rows: None,
             frequency: 60,
-            executed: Vec::new()
+            fps: FpsCounter::new(),
+            speed: 0,
+            instructions_per_frame: 11,
+            cycle: 0,
+            tracer: Tracer::new(64),
+            profiler: None,
+            blocks: BlockCache::new(),
+            keypad: Keypad::new(),
+            memory_view: MemoryView::new(config.font_address..config.font_address.saturating_add((SMALL_FONT_SIZE + BIG_FONT_SIZE) as u16)),
+            recent_writes: VecDeque::new(),
+            paused: false,
+            quit: false,
+            rom: None,
+            rom_name: String::new(),
+            font: Vec::new(),
+            save_path: None,
+            message: None,
         };
 
-        let fontset = Emulator::get_fontset();
+        emulator.load_font(&font)?;
+        Ok(emulator)
+    }
+
+    // Replaces the built-in font with the contents of a font file: the small
+    // font, optionally followed by the SCHIP big font. Without a big font the
+    // current one stays, and is kept with the new small font for resets.
+    pub fn load_font(&mut self, font: &[u8]) -> Result<(), Error> {
+        check_font_file(font)?;
+        self.memory.load(self.config.font_address as usize, font)?;
+        let mut image = font.to_vec();
+        if let Some(big) = self.font.get(font.len()..) {
+            image.extend_from_slice(big);
+        }
+        self.font = image;
+        Ok(())
+    }
+
+    // Loads a ROM at the configured load address and entry point.
+    pub fn load(&mut self, prog: Vec<u8>) -> Result<(), Error> {
+        self.load_rom(&Rom {
+            data: prog,
+            load_address: self.config.load_address,
+            entry_point: self.config.entry_point,
+        })
+    }
+
+    pub fn load_rom(&mut self, rom: &Rom) -> Result<(), Error> {
+        self.memory.load(rom.load_address as usize, &rom.data)?;
+        self.pc = rom.entry_point;
+        self.rom = Some(rom.clone());
+        Ok(())
+    }
+
+    // Replaces memory from address 0 with a raw dump, font included, and
+    // starts at the configured entry point.
+    pub fn load_image(&mut self, image: Vec<u8>) -> Result<(), Error> {
+        self.load_rom(&Rom {
+            data: image,
+            load_address: 0,
+            entry_point: self.config.entry_point,
+        })
+    }
+
+    // Reloads the ROM over whatever the program has changed and starts it
+    // again. The rest of memory is left alone.
+    pub fn reset(&mut self) {
+        self.v = [0; 16];
+        self.i = 0;
+        self.stack = Stack::new(self.config.stack_size);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.reset_calls();
+        }
+        self.display.clear();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.draw_flag = true;
+        self.waiting_for_vblank = false;
+        self.last_draw_rows = None;
+        self.recent_writes.clear();
+        self.pc = self.config.entry_point;
+        if let Some(rom) = self.rom.take() {
+            self.load_rom(&rom).expect("ROM no longer fits in memory");
+        }
+    }
 
-        emulator.memory[0x50..(0x50+fontset.len())].clone_from_slice(&fontset);
+    // Like turning the machine off and on again: memory is cleared and only
+    // the font and ROM are put back.
+    pub fn hard_reset(&mut self) {
+        self.memory.clear();
+        let font = std::mem::take(&mut self.font);
+        self.load_font(&font).expect("Font no longer fits in memory");
+        self.cycle = 0;
+        self.reset();
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    // Hooks see every fetch, read and write the program makes.
+    pub fn subscribe_memory(&mut self, hook: Hook) {
+        self.memory.subscribe(hook);
+    }
+
+    pub fn pixels(&self) -> &[[bool; 64]; 32] {
+        &self.display.pixels
+    }
+
+    // The active calls, outermost first.
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.stack.frames()
+    }
 
-        //println!("{:02x?}", emulator.memory);
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    // Presses or releases a CHIP-8 key as if from a terminal that reports
+    // releases, for driving a program without one.
+    pub fn set_key(&mut self, key: u8, down: bool) {
+        let kind = if down { KeyEventKind::Press } else { KeyEventKind::Release };
+        let event = KeyEvent::new_with_kind(KeyCode::Char(keypad::host_key(key)), KeyModifiers::NONE, kind);
+        self.keypad.track(&event);
+    }
 
-        emulator
+    // How long a key counts as held after its last press or auto-repeat, on
+    // terminals that don't report key releases.
+    pub fn set_key_hold_time(&mut self, hold_time: Duration) {
+        self.keypad.set_hold_time(hold_time);
     }
 
-    pub fn load(&mut self, prog: Vec<u8>) {
-        self.memory[0x200..(0x200+prog.len())].clone_from_slice(&prog[0..prog.len()]);
-        self.pc = 0x200;
+    // Shown in the status panel.
+    pub fn set_rom_name(&mut self, name: &str) {
+        self.rom_name = name.to_string();
+    }
+
+    pub fn set_save_path(&mut self, path: &str) {
+        self.save_path = Some(path.to_string());
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions: u32) {
+        self.instructions_per_frame = instructions;
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
+
+    // Writes out what is left of the trace, or reports why it couldn't be
+    // written.
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        self.tracer.flush()
+    }
+
+    pub fn enable_profiler(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
     }
 
     
-    fn fetch(&mut self) -> u16 {
-        let opcode1 = self.memory[(self.pc as usize)] as u16;
-        self.pc += 1;
-        let opcode2 = self.memory[(self.pc as usize)] as u16;
-        self.pc += 1;
-        opcode1 << 8 | opcode2
+    // Returns the instruction word at PC along with what it decodes to.
+    // Memory keeps the decoded form of everything already run.
+    fn fetch(&mut self) -> Result<(u16, Opcode), Error> {
+        let fetched = self.memory.fetch_decoded(self.pc as usize, Self::decode)?;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(fetched)
     }
     
-    fn decode(instruction: u16) -> Opcode {
-        if instruction == 238 {
-            let x = 3;
-        }
-        match instruction {
-            0x00e0 => Opcode::ClearScreen,
-            0x00ee => Opcode::Return,
-            0x1000..=0x1fff => Opcode::Jump(instruction& 0x0fff),
-            0x2000..=0x2fff => Opcode::Call(instruction& 0x0fff),
-            0x3000..=0x3fff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let val = (instruction & 0x00ff) as u8;
-                Opcode::Skip(reg, val)
-            },
-            0x4000..=0x4fff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let val = (instruction & 0x00ff) as u8;
-                Opcode::SkipNotEqual(reg, val)
-            },
-            0x5000..=0x5ff0 => {
-                let reg1 = (instruction & 0x0f00) >> 8;
-                let reg2 = (instruction & 0x00f0) >> 4;
-                Opcode::SkipRegEqual(reg1, reg2)
-            },
-            0x6000..=0x6fff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let value = (instruction & 0x00ff) as u8;
-                Opcode::Set(reg, value)
-            },
-            0x7000..=0x7fff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let value = (instruction & 0x00ff) as u8;
-                Opcode::IncrementReg(reg, value)
-            },
-            0x8000..=0x8fff => {
-                let reg1 = (instruction & 0x0f00) >> 8;    
-                let reg2 = (instruction & 0x00f0) >> 4;
-                let last_digit = instruction & 0x000f;
-                match last_digit {
-                    0x0 => Opcode::CopyReg(reg1, reg2),
-                    0x1 => Opcode::BitwiseOr(reg1, reg2),
-                    0x2 => Opcode::BitwiseAnd(reg1, reg2),
-                    0x3 => Opcode::BitwiseXor(reg1, reg2),
-                    0x4 => Opcode::AddReg(reg1, reg2),
-                    0x5 => Opcode::SubtractReg(reg1, reg2),
-                    0x6 => Opcode::BitwiseRight(reg1, reg2),               
-                    0x7 => Opcode::NegativeSubtractReg(reg1, reg2),
-                    0xe => Opcode::BitwiseLeft(reg1, reg2),                        
-                    _ => panic!("Oh no!")
-                }
-            },                                               
-            0x9000..=0x9ff0 => {
-                let reg1 = (instruction & 0x0f00) >> 8;
-                let reg2 = (instruction & 0x00f0) >> 4;
-                Opcode::SkipRegNotEqual(reg1, reg2)
-            },            
-            0xa000..=0xafff => Opcode::SetI(instruction & 0x0fff),
-            0xb000..=0xbfff => Opcode::JumpOffset(instruction & 0x0fff),
-            0xc000..=0xcfff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let value = (instruction & 0x00ff) as u8;
-                Opcode::RandomAnd(reg, value)
-            },
-            0xd000..=0xdfff => {
-                let x = (instruction & 0x0f00) >> 8;
-                let y = (instruction & 0x00f0) >> 4;
-                let rows = (instruction & 0x000f) as u8;
-                Opcode::Draw(x as u8,y as u8, rows)
-            },
-            0xf000..=0xffff => {
-                let reg = (instruction & 0x0f00) >> 8;
-                let command_digits = instruction & 0x00ff;
-                match command_digits {
-                    0x07 => Opcode::CopyDelayToReg((reg) as u8),
-                    0x0a => Opcode::WaitForKeyPress(reg),
-                    0x15 => Opcode::SetDelayFromReg(reg),
-                    0x18 => Opcode::SetSoundFromReg(reg),
-                    0x1e => Opcode::AddI(reg),
-                    0x29 => Opcode::SetIToFontDigit(reg),
-                    0x33 => Opcode::BinaryCodeI(reg),
-                    0x55 => Opcode::CopyRegistersToI(reg),
-                    0x65 => Opcode::CopyIToRegisters(reg),
-                    _ => panic!("Oh no!")
-                }
-            },
-            0xe09e..=0xef9e => Opcode::SkipKeyPressed((instruction & 0x0f00) >> 8),
-            0xe0a1..=0xefa1 => Opcode::SkipKeyNotPressed((instruction & 0x0f00) >> 8),
-            _ => panic!("Oh no!")
+    // Decodes on the nibbles of the instruction. Words that aren't a valid
+    // instruction decode to Unknown, and 0nnn machine code calls to Sys.
+    pub fn decode(instruction: u16) -> Opcode {
+        let x = (instruction & 0x0f00) >> 8;
+        let y = (instruction & 0x00f0) >> 4;
+        let n = (instruction & 0x000f) as u8;
+        let kk = (instruction & 0x00ff) as u8;
+        let nnn = instruction & 0x0fff;
+
+        match (instruction >> 12, x, y, n) {
+            (0x0, 0x0, 0xe, 0x0) => Opcode::ClearScreen,
+            (0x0, 0x0, 0xe, 0xe) => Opcode::Return,
+            (0x0, 0x0, 0xf, 0xd) => Opcode::Exit,
+            (0x0, _, _, _) => Opcode::Sys(nnn),
+            (0x1, _, _, _) => Opcode::Jump(nnn),
+            (0x2, _, _, _) => Opcode::Call(nnn),
+            (0x3, _, _, _) => Opcode::Skip(x, kk),
+            (0x4, _, _, _) => Opcode::SkipNotEqual(x, kk),
+            (0x5, _, _, 0x0) => Opcode::SkipRegEqual(x, y),
+            (0x6, _, _, _) => Opcode::Set(x, kk),
+            (0x7, _, _, _) => Opcode::IncrementReg(x, kk),
+            (0x8, _, _, 0x0) => Opcode::CopyReg(x, y),
+            (0x8, _, _, 0x1) => Opcode::BitwiseOr(x, y),
+            (0x8, _, _, 0x2) => Opcode::BitwiseAnd(x, y),
+            (0x8, _, _, 0x3) => Opcode::BitwiseXor(x, y),
+            (0x8, _, _, 0x4) => Opcode::AddReg(x, y),
+            (0x8, _, _, 0x5) => Opcode::SubtractReg(x, y),
+            (0x8, _, _, 0x6) => Opcode::BitwiseRight(x, y),
+            (0x8, _, _, 0x7) => Opcode::NegativeSubtractReg(x, y),
+            (0x8, _, _, 0xe) => Opcode::BitwiseLeft(x, y),
+            (0x9, _, _, 0x0) => Opcode::SkipRegNotEqual(x, y),
+            (0xa, _, _, _) => Opcode::SetI(nnn),
+            (0xb, _, _, _) => Opcode::JumpOffset(nnn),
+            (0xc, _, _, _) => Opcode::RandomAnd(x, kk),
+            (0xd, _, _, _) => Opcode::Draw(x as u8, y as u8, n),
+            (0xe, _, 0x9, 0xe) => Opcode::SkipKeyPressed(x),
+            (0xe, _, 0xa, 0x1) => Opcode::SkipKeyNotPressed(x),
+            (0xf, _, 0x0, 0x7) => Opcode::CopyDelayToReg(x as u8),
+            (0xf, _, 0x0, 0xa) => Opcode::WaitForKeyPress(x),
+            (0xf, _, 0x1, 0x5) => Opcode::SetDelayFromReg(x),
+            (0xf, _, 0x1, 0x8) => Opcode::SetSoundFromReg(x),
+            (0xf, _, 0x1, 0xe) => Opcode::AddI(x),
+            (0xf, _, 0x2, 0x9) => Opcode::SetIToFontDigit(x),
+            (0xf, _, 0x3, 0x0) => Opcode::SetIToBigFontDigit(x),
+            (0xf, _, 0x3, 0x3) => Opcode::BinaryCodeI(x),
+            (0xf, _, 0x5, 0x5) => Opcode::CopyRegistersToI(x),
+            (0xf, _, 0x6, 0x5) => Opcode::CopyIToRegisters(x),
+            _ => Opcode::Unknown(instruction)
         }
     }
     
-    fn execute(&mut self, opcode: Opcode) {
+    fn execute(&mut self, opcode: Opcode) -> Result<(), Error> {
         match opcode {
+            // Handled by `step`, which only gets here if they are ignored.
+            Opcode::Sys(_) | Opcode::Unknown(_) | Opcode::Exit => {},
             Opcode::ClearScreen => {
                 self.display.clear();
                 self.draw_flag = true;
             },
             Opcode::Return => {
-                self.pc = self.pop_stack();
+                self.pc = self.pop_stack(self.pc.wrapping_sub(2))?;
             },
             Opcode::Jump(addr) => {
                 self.pc = addr;
             },
             Opcode::Call(addr) => {
-                self.push_stack(self.pc);
+                self.push_stack(CallFrame { call_site: self.pc.wrapping_sub(2), target: addr, return_address: self.pc })?;
                 self.pc = addr;
             },
             Opcode::Skip(reg,val) => {
                 if val == self.v[reg as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Opcode::SkipNotEqual(reg,val) => {
                 if val != self.v[reg as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             Opcode::SkipRegEqual(reg1,reg2) => {
                 if self.v[reg1 as usize] == self.v[reg2 as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },                     
             Opcode::Set(v, n) => self.v[v as usize] = n,
             Opcode::IncrementReg(v, n) => self.v[v as usize] = self.v[v as usize].wrapping_add(n),
             Opcode::CopyReg(reg1, reg2) => self.v[reg1 as usize] = self.v[reg2 as usize],
-            Opcode::BitwiseOr(reg1, reg2) => self.v[reg1 as usize] |= self.v[reg2 as usize],
-            Opcode::BitwiseAnd(reg1, reg2) => self.v[reg1 as usize] &= self.v[reg2 as usize],
-            Opcode::BitwiseXor(reg1, reg2) => self.v[reg1 as usize] ^= self.v[reg2 as usize],
-            Opcode::AddReg(reg1, reg2) => {
-                let sum = self.v[reg1 as usize] as usize + self.v[reg2 as usize] as usize;
-                if sum > 255 {
-                    self.v[15] = 1;
-                    self.v[reg1 as usize] = (sum % 0x255) as u8;
-                }
-                else {
-                    self.v[reg1 as usize] = sum as u8;
+            // VF is always written after the result so that it ends up
+            // holding the flag when it is also the destination register.
+            Opcode::BitwiseOr(reg1, reg2) => {
+                self.v[reg1 as usize] |= self.v[reg2 as usize];
+                if self.config.quirks.vf_reset {
+                    self.v[15] = 0;
                 }
             },
-            Opcode::SubtractReg(reg1, reg2) => {
-                let v1 = self.v[reg1 as usize] as i16;
-                let v2 = self.v[reg2 as usize] as i16;
-                self.v[reg1 as usize] = (v1-v2) as u8;
-                if v1 >= v2 {
-                    self.v[15] = 1;
+            Opcode::BitwiseAnd(reg1, reg2) => {
+                self.v[reg1 as usize] &= self.v[reg2 as usize];
+                if self.config.quirks.vf_reset {
+                    self.v[15] = 0;
                 }
-                else {
+            },
+            Opcode::BitwiseXor(reg1, reg2) => {
+                self.v[reg1 as usize] ^= self.v[reg2 as usize];
+                if self.config.quirks.vf_reset {
                     self.v[15] = 0;
                 }
             },
-            Opcode::BitwiseRight(reg1, _) => {
-                let least_bit = (self.v[reg1 as usize] & 1) as u8;
-                self.v[15] = least_bit;
-                self.v[reg1 as usize] >>= 1;
+            Opcode::AddReg(reg1, reg2) => {
+                let (sum, carry) = self.v[reg1 as usize].overflowing_add(self.v[reg2 as usize]);
+                self.v[reg1 as usize] = sum;
+                self.v[15] = carry as u8;
+            },
+            Opcode::SubtractReg(reg1, reg2) => {
+                let (difference, borrow) = self.v[reg1 as usize].overflowing_sub(self.v[reg2 as usize]);
+                self.v[reg1 as usize] = difference;
+                self.v[15] = !borrow as u8;
+            },
+            Opcode::BitwiseRight(reg1, reg2) => {
+                let source = if self.config.quirks.shift_vy { reg2 } else { reg1 };
+                let value = self.v[source as usize];
+                self.v[reg1 as usize] = value >> 1;
+                self.v[15] = value & 1;
             },
             Opcode::NegativeSubtractReg(reg1, reg2) => {
-                let v1 = self.v[reg1 as usize];
-                let v2 = self.v[reg2 as usize];
-                if v2 > v1 {
-                    self.v[reg1 as usize] = v2 - v1;
-                    self.v[15] = 1;
-                }
-                else {
-                    self.v[15] = 0;
-                }                
+                let (difference, borrow) = self.v[reg2 as usize].overflowing_sub(self.v[reg1 as usize]);
+                self.v[reg1 as usize] = difference;
+                self.v[15] = !borrow as u8;
             },
-            Opcode::BitwiseLeft(reg1, _) => {
-                let most_significant_bit = (self.v[reg1 as usize] & 0b10000000) >> 7;
-                self.v[15] = most_significant_bit;
-                self.v[reg1 as usize] <<= 1;                
+            Opcode::BitwiseLeft(reg1, reg2) => {
+                let source = if self.config.quirks.shift_vy { reg2 } else { reg1 };
+                let value = self.v[source as usize];
+                self.v[reg1 as usize] = value << 1;
+                self.v[15] = (value & 0b10000000) >> 7;
             },
             Opcode::SkipRegNotEqual(reg1,reg2) => {
                 if self.v[reg1 as usize] != self.v[reg2 as usize] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },              
             Opcode::SetI(i) => self.i = i,
@@ -286,174 +611,479 @@ impl Emulator {
                 self.v[reg as usize] = rando & val;
             },
             Opcode::Draw(x, y, rows) => {
-                let vx = self.v[x as usize] % 64;
-                let vy = self.v[y as usize] % 32;
-                //println!("row count = {}", rows);
-                //let ourrange = &self.memory[self.I as usize..=(self.I as usize+rows as usize)];
-                //println!("I-slice = {:04x?}", ourrange);                
-                for i in 0..rows {
-                    let row_byte = self.memory[self.i as usize +i as usize];
-                    let x_coord = (vx % 64) as usize;
-                    let y_coord = ((vy + i) % 32) as usize;                        
-                    let collision = self.display.write_row_buffer(x_coord, y_coord, row_byte);
-                    if collision {
-                        self.v[15] = 1;
-                    }
+                let mut sprite = Vec::with_capacity(rows as usize);
+                for row in 0..rows as u16 {
+                    sprite.push(self.memory.read(self.index_addr(row))?);
                 }
+                let vx = self.v[x as usize] as usize;
+                let vy = self.v[y as usize] as usize;
+                let collided_rows = self.display.write_sprite(vx, vy, &sprite);
+                self.v[15] = if self.config.quirks.collision_rows {
+                    collided_rows as u8
+                } else {
+                    (collided_rows > 0) as u8
+                };
+                self.waiting_for_vblank = self.config.quirks.display_wait;
+                self.last_draw_rows = Some(rows);
 
                 self.draw_flag = true;
             },
             Opcode::SkipKeyPressed(reg1) => {
-                let val = self.v[reg1 as usize];
-                if let Some(key) = Keypad::pressed() {
-                    if key == val as u32 {
-                        self.pc += 2;
-                    }
-                }             
+                if self.keypad.is_down(self.v[reg1 as usize]) {
+                    self.skip();
+                }
             },
             Opcode::SkipKeyNotPressed(reg1) => {
-                let val = self.v[reg1 as usize];
-                if let Some(key) = Keypad::pressed() {
-                    if key != val as u32 {
-                        self.pc += 2;
-                    }
+                if !self.keypad.is_down(self.v[reg1 as usize]) {
+                    self.skip();
                 }
-                else {
-                    self.pc += 2;
-                }                   
             },
             Opcode::CopyDelayToReg(reg1) => self.v[reg1 as usize] = self.delay_timer,
             Opcode::WaitForKeyPress(reg1) => {
-                loop {
-                    if let Some(key) = Keypad::pressed() {
-                        self.v[reg1 as usize] = key as u8;
-                        break;
-                    }                         
+                // Repeat the instruction until a key arrives so that timers
+                // keep running while waiting.
+                match self.keypad.take_press() {
+                    Some(key) => self.v[reg1 as usize] = key,
+                    None => self.pc = self.pc.wrapping_sub(2)
                 }
             },
             Opcode::SetDelayFromReg(reg1) => self.delay_timer = self.v[reg1 as usize],
             Opcode::SetSoundFromReg(reg1) => self.sound_timer = self.v[reg1 as usize],
-            Opcode::AddI(val) => self.i += val,
+            Opcode::AddI(reg) => self.i = self.i.wrapping_add(self.v[reg as usize] as u16),
             Opcode::SetIToFontDigit(reg) => {
-                let val = self.v[reg as usize];
-                self.i = 0x50 + val as u16;
+                let digit = self.v[reg as usize] & 0xf;
+                self.i = self.config.font_address + digit as u16 * 5;
+            },
+            Opcode::SetIToBigFontDigit(reg) => {
+                let digit = self.v[reg as usize] & 0xf;
+                self.i = self.config.font_address + SMALL_FONT_SIZE as u16 + digit as u16 * 10;
             },
             Opcode::BinaryCodeI(reg) => {
-                let val_string = format!("{:03}",self.v[reg as usize]);
-                let digits: Vec<u8> = val_string
-                    .chars()
-                    .map(|d| d.to_digit(10).unwrap() as u8)
-                    .collect();
-
-                self.memory[self.i as usize] = digits[0];
-                self.memory[(self.i+1) as usize] = digits[1];
-                self.memory[(self.i+2) as usize] = digits[2];
-
+                let val = self.v[reg as usize];
+                for (offset, digit) in [val / 100, val / 10 % 10, val % 10].into_iter().enumerate() {
+                    self.store(self.index_addr(offset as u16), digit)?;
+                }
             },
             Opcode::CopyRegistersToI(n) => {
                 for i in 0..=n {
-                    self.memory[(self.i+i) as usize] = self.v[i as usize];
+                    self.store(self.index_addr(i), self.v[i as usize])?;
                 }
+                self.increment_index(n);
             },
             Opcode::CopyIToRegisters(n) => {
                 for i in 0..=n {
-                    self.v[i as usize] = self.memory[(self.i+i) as usize];
+                    self.v[i as usize] = self.memory.read(self.index_addr(i))?;
                 }
+                self.increment_index(n);
             }
         }
-        self.executed.push(opcode);
+        Ok(())
     }
 
-    fn push_stack(&mut self, val: u16) {
-        self.stack.push(val);
-        self.sp += 1;
+    fn skip(&mut self) {
+        self.pc = self.pc.wrapping_add(2);
     }
 
-    fn pop_stack(&mut self) -> u16 {
-        self.sp -=1;
-        self.stack.pop().unwrap()
+    // Writes on behalf of the host or debugger, ignoring write protection.
+    pub fn write_memory(&mut self, addr: u16, val: u8) -> Result<(), Error> {
+        if addr as usize >= self.memory.len() {
+            return Err(Error::OutOfBounds { addr: addr as usize });
+        }
+        self.memory.poke(addr as usize, val);
+        self.note_write(addr);
+        Ok(())
     }
 
-    fn get_fontset() -> [u8; 80] {
-        let fontset: [u8; 80] =
-        [   0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80  // F
-        ];
+    // The address `offset` bytes past I. Out of range addresses are left for
+    // memory to reject unless the configuration wraps them.
+    fn index_addr(&self, offset: u16) -> usize {
+        let addr = self.i as usize + offset as usize;
+        match self.config.index_overflow {
+            IndexOverflow::Wrap => addr % self.memory.len(),
+            IndexOverflow::Error => addr,
+        }
+    }
 
-        fontset
+    fn increment_index(&mut self, x: u16) {
+        let step = match self.config.quirks.index_increment {
+            IndexIncrement::Unchanged => return,
+            IndexIncrement::ByX => x,
+            IndexIncrement::ByXPlusOne => x + 1,
+        };
+        self.i = self.i.wrapping_add(step);
     }
 
-    pub fn run(&mut self) {           
-        let mut last_cycle_time =SystemTime::now();
-        let max_duration = (1000_f64)/(self.frequency as f64);
+    fn store(&mut self, addr: usize, val: u8) -> Result<(), Error> {
+        self.memory.write(addr, val)?;
+        self.note_write(addr as u16);
+        Ok(())
+    }
 
-        loop {
-            let opcode = self.fetch();
-            let instruction = Self::decode(opcode);
-            if opcode == 0x0 { break; }
-            //println!("{:04x}: Opcode =      {:04x?}", self.pc-2, opcode);
-            //println!("{:04x}: Instruction = {:?}", self.pc-2, instruction);
-
-            self.execute(instruction);
-            if self.draw_flag {
-                self.draw();
-            }
-            
-            let time_elapsed = SystemTime::now().duration_since(last_cycle_time).unwrap().as_millis();
-            if time_elapsed >= max_duration as u128 {
-                if self.delay_timer > 0 {
-                    self.delay_timer-= 1;
+    fn note_write(&mut self, addr: u16) {
+        if self.recent_writes.len() == 16 {
+            self.recent_writes.pop_front();
+        }
+        self.recent_writes.push_back(addr);
+    }
+
+    fn push_stack(&mut self, frame: CallFrame) -> Result<(), Error> {
+        self.stack.push(frame)?;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter_call(frame.target);
+        }
+        Ok(())
+    }
+
+    fn pop_stack(&mut self, addr: u16) -> Result<u16, Error> {
+        let frame = self.stack.pop(addr)?;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.leave_call();
+        }
+        Ok(frame.return_address)
+    }
+
+    // Executes a single instruction, or reports why the program can't go on.
+    // A halted program's PC is left on the instruction that halted it.
+    pub fn step(&mut self) -> Result<Option<Halt>, Error> {
+        let pc = self.pc;
+        if pc as usize + 1 >= self.memory.len() {
+            return Ok(Some(Halt::EndOfMemory));
+        }
+        let (opcode, instruction) = self.fetch()?;
+
+        match instruction {
+            Opcode::Exit => {
+                self.pc = pc;
+                return Ok(Some(Halt::Exit));
+            },
+            Opcode::Jump(addr) if addr == pc => {
+                self.pc = pc;
+                return Ok(Some(Halt::InfiniteLoop(pc)));
+            },
+            _ => {}
+        }
+
+        let unknown = match instruction {
+            Opcode::Unknown(_) => true,
+            Opcode::Sys(_) => !self.config.sys_noop,
+            _ => false
+        };
+        if unknown {
+            match self.config.unknown_opcode {
+                UnknownOpcodePolicy::Skip => {},
+                UnknownOpcodePolicy::Halt => {
+                    self.pc = pc;
+                    return Ok(Some(Halt::UnknownOpcode { addr: pc, opcode }));
+                },
+                UnknownOpcodePolicy::Trap => {
+                    self.pc = pc;
+                    return Err(Error::UnknownOpcode { addr: pc, opcode });
                 }
-                last_cycle_time = SystemTime::now();
             }
-        }    
-    }
-
-    fn draw(&mut self) {
-        print!("{}[2J", 27 as char);
-        print!("╔");
-        let width = self.display.pixels[0].len();
-        print!("{:═<1$}", "", width);
-        // print ═ n times, where n is display width -2
-        println!("╗");
-        for row in self.display.pixels {
-            print!("║");
-            for pixel in row {
-                if pixel {
-                    print!("▉"); //("█") // ▀
-                }
-                else {
-                    print!(" ");
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(pc, instruction);
+        }
+        if let Err(error) = self.execute(instruction) {
+            self.pc = pc;
+            return Err(error);
+        }
+        self.retire(pc, opcode, instruction);
+
+        // PC is 16 bits, so carrying on past the last word of a full 64K
+        // memory wraps it around to 0.
+        let transfers = matches!(instruction,
+            Opcode::Jump(_) | Opcode::JumpOffset(_) | Opcode::Call(_) | Opcode::Return);
+        if self.pc < pc && !transfers {
+            self.pc = pc;
+            return Ok(Some(Halt::EndOfMemory));
+        }
+        Ok(None)
+    }
+
+    // Traces an instruction that has run and counts the cycle.
+    fn retire(&mut self, pc: u16, raw: u16, opcode: Opcode) {
+        self.tracer.record(TraceEntry {
+            cycle: self.cycle,
+            pc,
+            raw,
+            opcode,
+            v: self.v,
+            i: self.i
+        });
+        self.cycle += 1;
+    }
+
+    // The recompiled block at PC, if the backend uses them. Memory hooks
+    // expect to see every fetch, which blocks skip, so they keep the
+    // interpreter.
+    fn block_at_pc(&mut self) -> Option<Arc<Block>> {
+        if self.config.backend == Backend::Interpreter || self.memory.has_hooks() {
+            return None;
+        }
+        self.blocks.get(&mut self.memory, self.pc, &self.config)
+    }
+
+    // Runs one 60Hz frame worth of instructions and then ticks the timers.
+    pub fn run_frame(&mut self) -> Result<Option<Halt>, Error> {
+        let mut remaining = self.instructions_per_frame as usize;
+        while remaining > 0 {
+            match self.block_at_pc() {
+                Some(block) => {
+                    let count = block.instructions.len().min(remaining);
+                    if self.config.backend == Backend::Differential {
+                        self.run_block_checked(&block, count)?;
+                    } else {
+                        self.run_block(&block, count)?;
+                    }
+                    remaining -= count;
+                },
+                None => {
+                    if let Some(halt) = self.step()? {
+                        return Ok(Some(halt));
+                    }
+                    remaining -= 1;
+                },
+            }
+            if self.waiting_for_vblank {
+                self.waiting_for_vblank = false;
+                break;
+            }
+        }
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+        Ok(None)
+    }
+
+    // Runs without a terminal: nothing is drawn and no keys are pressed.
+    // Stops early if the program halts.
+    pub fn run_frames(&mut self, frames: u32) -> Result<Option<Halt>, Error> {
+        for _ in 0..frames {
+            if let Some(halt) = self.run_frame()? {
+                return Ok(Some(halt));
+            }
+        }
+        Ok(None)
+    }
+
+    // Runs the program in the terminal until it halts. The CPU gets a thread
+    // of its own, while this one draws frames and passes input along. The
+    // final screen stays up with the reason until a key is pressed.
+    pub fn run(&mut self) -> Result<Halt, Error> {
+        let _terminal = Terminal::open();
+        let frames = FrameBuffer::new();
+        let (input, events) = mpsc::channel();
+        thread::scope(|scope| {
+            let frames = &frames;
+            let cpu = scope.spawn(move || self.run_cpu(events, frames));
+            renderer::run(frames, input, || cpu.is_finished());
+            cpu.join().expect("CPU thread panicked")
+        })
+    }
+
+    // The CPU thread. Frames run against a fixed schedule rather than
+    // sleeping a frame's length after each, so the rate stays steady. The
+    // input queue is drained at the start of every frame.
+    fn run_cpu(&mut self, input: Receiver<Event>, frames: &FrameBuffer) -> Result<Halt, Error> {
+        let mut deadline = Instant::now();
+        self.fps = FpsCounter::new();
+        self.draw_flag = true;
+        let halt = loop {
+            let count = if self.paused {
+                self.wait_while_paused(&input, frames)
+            } else {
+                self.handle_input(&input);
+                1 << self.speed.max(0)
+            };
+            if self.quit {
+                break Halt::Quit;
+            }
+            let new_fps = self.fps.tick(count);
+            if let Some(halt) = self.run_frames(count)? {
+                break halt;
+            }
+            if self.paused {
+                self.memory_view.follow(self.pc);
+                self.draw_flag = true;
+            }
+            if self.draw_flag || new_fps || self.keypad.keys_down() != self.drawn_keys
+                || (self.delay_timer, self.sound_timer) != self.drawn_timers {
+                frames.publish(self.frame(None));
+            }
+
+            // Slow motion stretches each frame instead of skipping any. A
+            // schedule that has fallen more than a frame behind starts over.
+            let frame_duration = Duration::from_secs_f64((1 << (-self.speed).max(0)) as f64 / self.frequency as f64);
+            deadline += frame_duration;
+            let now = Instant::now();
+            if self.paused || deadline + frame_duration < now {
+                deadline = now;
+            } else if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        };
+        if halt != Halt::Quit {
+            frames.publish(self.frame(Some(format!("Halted: {}. Press any key to exit.", halt))));
+            while let Ok(event) = input.recv() {
+                if self.keypad.handle(&event).is_some() {
+                    break;
                 }
             }
-            println!("║");
-        }
-        print!("╚");
-        print!("{:═<1$}", "", width);
-        println!("╝");
-        println!();
-        print!("DT");
-        println!();
-        println!("Last executed: {:?}", self.executed.last().unwrap());
-        for (i,val) in self.v.iter().enumerate() {
-            print!("V{} {:x}  ", i, val);
-        }
-        println!();
+        }
+        Ok(halt)
+    }
+
+    // The renderer hangs up when the terminal fails, which quits.
+    fn handle_input(&mut self, input: &Receiver<Event>) {
+        self.keypad.begin_frame();
+        loop {
+            let event = match input.try_recv() {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.apply_hotkey(Hotkey::Quit);
+                    break;
+                },
+            };
+            if let Some(hotkey) = self.keypad.handle(&event).as_ref().and_then(Hotkey::from_event) {
+                self.apply_hotkey(hotkey);
+                self.draw_flag = true;
+            }
+        }
+    }
+
+    fn apply_hotkey(&mut self, hotkey: Hotkey) {
+        match hotkey {
+            Hotkey::Debugger => self.memory_view.visible = !self.memory_view.visible,
+            Hotkey::Pause => {
+                self.paused = !self.paused;
+                self.memory_view.follow(self.pc);
+            },
+            // Only does anything while paused, see `wait_while_paused`.
+            Hotkey::FrameAdvance => self.paused = true,
+            Hotkey::Reset => {
+                self.reset();
+                self.message = Some(String::from("Reset"));
+            },
+            Hotkey::HardReset => {
+                self.hard_reset();
+                self.message = Some(String::from("Hard reset"));
+            },
+            Hotkey::Save => self.message = Some(match &self.save_path {
+                Some(path) => match fs::write(path, self.memory.as_slice()) {
+                    Ok(()) => format!("Saved memory to {}", path),
+                    Err(error) => format!("Unable to save memory to {}: {}", path, error),
+                },
+                None => String::from("No save file"),
+            }),
+            Hotkey::SlowDown => self.speed = (self.speed - 1).max(-MAX_SPEED),
+            Hotkey::SpeedUp => self.speed = (self.speed + 1).min(MAX_SPEED),
+            Hotkey::Quit => self.quit = true,
+        }
+    }
+
+    // Handles input until the user resumes, quits or asks for the next frame,
+    // and returns the number of frames to run.
+    fn wait_while_paused(&mut self, input: &Receiver<Event>, frames: &FrameBuffer) -> u32 {
+        loop {
+            let Ok(event) = input.recv() else {
+                self.quit = true;
+                return 0;
+            };
+            // Key presses for the memory editor don't reach the CHIP-8
+            // keypad. Releases still do, so no key is left held.
+            let key = match event {
+                Event::Key(key) if self.memory_view.visible && key.kind != KeyEventKind::Release => Some(key),
+                _ => self.keypad.handle(&event),
+            };
+            let Some(key) = key else {
+                continue;
+            };
+            match Hotkey::from_event(&key) {
+                // Keys pressed while paused aren't for Fx0A in the next frame.
+                Some(Hotkey::FrameAdvance) => {
+                    self.keypad.begin_frame();
+                    return 1;
+                },
+                Some(hotkey) => self.apply_hotkey(hotkey),
+                None if self.memory_view.visible => {
+                    if let Some((addr, val)) = self.memory_view.handle_key(key.code, self.memory.as_slice(), self.pc, self.i) {
+                        if let Err(error) = self.write_memory(addr, val) {
+                            self.message = Some(error.to_string());
+                        }
+                    }
+                },
+                None => {}
+            }
+            frames.publish(self.frame(None));
+            if !self.paused || self.quit {
+                return 0;
+            }
+        }
+    }
+
+    fn speed_label(&self) -> String {
+        match self.speed {
+            speed if speed >= 0 => format!("{}x", 1 << speed),
+            speed => format!("1/{}x", 1 << -speed),
+        }
+    }
+
+    // Shows the sprite at I using the size of the last executed Draw, or the
+    // largest 8 pixel wide sprite when there wasn't one.
+    fn sprite_at_i(&self) -> Vec<String> {
+        let (len, wide) = match self.last_draw_rows {
+            Some(0) => (32, true),
+            Some(rows) => (rows as usize, false),
+            None => (15, false),
+        };
+        let memory = self.memory.as_slice();
+        let start = (self.i as usize).min(memory.len());
+        let end = (start + len).min(memory.len());
+        let mut lines = vec![format!("Sprite at I ({:03X}):", self.i)];
+        lines.extend(sprite_view::render_sprite(&memory[start..end], wide));
+        lines
+    }
+
+    // Copies out what the renderer needs to draw the current state.
+    fn frame(&mut self, halted: Option<String>) -> Frame {
+        let mut debugger = Vec::new();
+        if self.memory_view.visible {
+            if !self.paused {
+                self.memory_view.follow(self.pc);
+            }
+            let recent_writes: Vec<u16> = self.recent_writes.iter().copied().collect();
+            debugger = self.memory_view.render(self.memory.as_slice(), self.pc, self.i, &recent_writes, self.paused);
+            debugger.extend(self.sprite_at_i());
+        }
         self.draw_flag = false;
+        self.drawn_keys = self.keypad.keys_down();
+        self.drawn_timers = (self.delay_timer, self.sound_timer);
+        Frame {
+            pixels: self.display.pixels,
+            keys_down: self.drawn_keys,
+            status: Status {
+                rom_name: self.rom_name.clone(),
+                fps: self.fps.fps(),
+                speed: self.speed_label(),
+                instructions_per_frame: self.instructions_per_frame,
+                paused: self.paused,
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+                pc: self.pc,
+                i: self.i,
+                v: self.v,
+            },
+            last_executed: self.tracer.last().map(|last| format!("{:04X}: {}", last.pc, last.opcode)),
+            message: self.message.clone(),
+            debugger,
+            halted,
+        }
     }
 
-}
\ No newline at end of file
+}
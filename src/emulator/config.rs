@@ -0,0 +1,163 @@
+use crate::emulator::font::Font;
+use crate::emulator::memory::{CHIP8_MEMORY_SIZE, INTERPRETER_END};
+use crate::emulator::stack::{SCHIP_STACK_SIZE, VIP_STACK_SIZE};
+
+// Behaviour that differs between CHIP-8 interpreters. The defaults follow
+// the common modern interpretation; presets select a specific machine.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quirks {
+    // 8xy1, 8xy2 and 8xy3 clear VF, as the COSMAC VIP interpreter did.
+    pub vf_reset: bool,
+    // 8xy6 and 8xyE shift Vy into Vx, as the COSMAC VIP interpreter did,
+    // rather than shifting Vx in place.
+    pub shift_vy: bool,
+    // How far Fx55 and Fx65 move I.
+    pub index_increment: IndexIncrement,
+    // Sprites wrap around the screen edges instead of being clipped.
+    pub wrap_sprites: bool,
+    // Dxyn sets VF to the number of rows that collided, as SCHIP and XO-CHIP
+    // do, rather than to 1.
+    pub collision_rows: bool,
+    // Dxyn waits for the vertical blank, so no more instructions run in the
+    // frame after a draw.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            vf_reset: true,
+            shift_vy: true,
+            index_increment: IndexIncrement::ByXPlusOne,
+            wrap_sprites: false,
+            collision_rows: false,
+            display_wait: true,
+        }
+    }
+
+    // SCHIP 1.1 on the HP 48, which otherwise matches the modern defaults.
+    pub fn schip() -> Quirks {
+        Quirks {
+            collision_rows: true,
+            ..Quirks::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IndexIncrement {
+    // SCHIP and most modern interpreters leave I alone.
+    #[default]
+    Unchanged,
+    // CHIP-48 left I pointing at the last register stored or loaded.
+    ByX,
+    // The COSMAC VIP left I just past it.
+    ByXPlusOne,
+}
+
+// What Dxyn, Fx33, Fx55 and Fx65 do when I plus the offset runs past the end
+// of memory.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IndexOverflow {
+    // Carry on from the start of memory.
+    #[default]
+    Wrap,
+    // Stop with an out of bounds error.
+    Error,
+}
+
+// What to do with a word that doesn't decode to an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum UnknownOpcodePolicy {
+    // Stop running, as if the program had ended.
+    Halt,
+    // Carry on with the next instruction.
+    Skip,
+    // Stop and report an error to the host.
+    #[default]
+    Trap,
+}
+
+// How instructions are run.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    // Fetch, decode and execute one instruction at a time.
+    #[default]
+    Interpreter,
+    // Translate straight-line runs of instructions into micro-ops once and
+    // run those from then on.
+    Recompiler,
+    // Run every block through both and stop with an error where they
+    // disagree. Slow; for testing the recompiler.
+    Differential,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub quirks: Quirks,
+    // Number of nested calls before 2nnn overflows the stack.
+    pub stack_size: usize,
+    // 4K for CHIP-8 and SCHIP, 64K for XO-CHIP.
+    pub memory_size: usize,
+    // Make 0x000-0x1FF read only to the program.
+    pub protect_interpreter: bool,
+    // Where `Emulator::load` puts a ROM and where execution starts.
+    pub load_address: u16,
+    pub entry_point: u16,
+    // Treat 0nnn machine code calls as no-ops instead of unknown opcodes.
+    pub sys_noop: bool,
+    pub unknown_opcode: UnknownOpcodePolicy,
+    pub index_overflow: IndexOverflow,
+    // The small font goes at `font_address`, the SCHIP big font right after.
+    pub font: Font,
+    pub font_address: u16,
+    pub backend: Backend,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            quirks: Quirks::default(),
+            stack_size: SCHIP_STACK_SIZE,
+            memory_size: CHIP8_MEMORY_SIZE,
+            protect_interpreter: false,
+            load_address: INTERPRETER_END as u16,
+            entry_point: INTERPRETER_END as u16,
+            sys_noop: false,
+            unknown_opcode: UnknownOpcodePolicy::default(),
+            index_overflow: IndexOverflow::default(),
+            font: Font::default(),
+            font_address: 0x50,
+            backend: Backend::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn cosmac_vip() -> Config {
+        Config {
+            quirks: Quirks::cosmac_vip(),
+            stack_size: VIP_STACK_SIZE,
+            font: Font::CosmacVip,
+            ..Config::default()
+        }
+    }
+
+    // The ETI 660 kept its interpreter and display below 0x600.
+    pub fn eti_660() -> Config {
+        Config {
+            load_address: 0x600,
+            entry_point: 0x600,
+            font: Font::Eti660,
+            ..Config::cosmac_vip()
+        }
+    }
+
+    pub fn schip() -> Config {
+        Config {
+            quirks: Quirks::schip(),
+            stack_size: SCHIP_STACK_SIZE,
+            ..Config::default()
+        }
+    }
+}
@@ -1,44 +1,59 @@
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+// The sprite's starting position always wraps onto the screen. Whatever runs
+// off the right or bottom edge is either clipped, as on the COSMAC VIP, or
+// wrapped around to the other side.
 pub struct Display {
-    pub pixels: [[bool;64];32]
+    pub pixels: [[bool; WIDTH]; HEIGHT],
+    wrap: bool,
 }
 
 impl Display {
-    pub fn new() -> Display {
+    pub fn new(wrap: bool) -> Display {
         Display {
-            pixels: [[false; 64];32]
+            pixels: [[false; WIDTH]; HEIGHT],
+            wrap,
         }
     }
 
     pub fn clear(&mut self) {
-        self.pixels = [[false; 64];32];
+        self.pixels = [[false; WIDTH]; HEIGHT];
     }
 
-    pub fn write_sprite(&mut self, x:usize, y:usize, sprite_bytes: &[u8]) -> bool {
-        let mut collision = false;
+    // XORs a sprite onto the screen, one byte per row, and returns the number
+    // of rows in which a pixel was turned off.
+    pub fn write_sprite(&mut self, x: usize, y: usize, sprite_bytes: &[u8]) -> usize {
+        let (x, y) = (x % WIDTH, y % HEIGHT);
+        let mut collided_rows = 0;
         for (i, byte) in sprite_bytes.iter().enumerate() {
-            collision |= self.write_row_buffer(x, y+i, *byte);
+            let row = match y + i {
+                row if row < HEIGHT => row,
+                row if self.wrap => row % HEIGHT,
+                _ => break,
+            };
+            if self.write_row(x, row, *byte) {
+                collided_rows += 1;
+            }
         }
-        collision
+        collided_rows
     }
 
-    pub fn write_row_buffer(&mut self, x:usize,y:usize, row_as_byte: u8) -> bool {
+    fn write_row(&mut self, x: usize, y: usize, row_as_byte: u8) -> bool {
         let mut collision = false;
         for i in 0..8 {
-            let bit_value = (( row_as_byte >> (7-i) ) & 1) > 0;
-            //println!("i = {}, j = {}, x = {}, y = {}, row_byte = {}, bit = {}", 
-            //    i, j, x_coord, y_coord, row_byte, bit_value);
-            let screen_pixel = &mut (self.pixels[y][(x+i)%64]);
-            if bit_value {
-                if *screen_pixel {
-                    *screen_pixel = false;
-                    collision = true;
-                }
-                else { 
-                    *screen_pixel = true;
-                }
+            if (row_as_byte >> (7 - i)) & 1 == 0 {
+                continue;
             }
+            let column = match x + i {
+                column if column < WIDTH => column,
+                column if self.wrap => column % WIDTH,
+                _ => break,
+            };
+            let screen_pixel = &mut self.pixels[y][column];
+            collision |= *screen_pixel;
+            *screen_pixel = !*screen_pixel;
         }
-
         collision
     }
-}
\ No newline at end of file
+}
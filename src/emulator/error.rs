@@ -0,0 +1,38 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    UnknownOpcode { addr: u16, opcode: u16 },
+    StackOverflow { addr: u16 },
+    StackUnderflow { addr: u16 },
+    RomTooLarge { size: usize, available: usize },
+    OutOfBounds { addr: usize },
+    WriteProtected { addr: usize },
+    InvalidHeader,
+    InvalidFont { size: usize },
+    BackendMismatch { addr: u16, difference: String },
+    MemoryTooLarge { size: usize },
+    FontDoesNotFit { addr: u16, memory_size: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::UnknownOpcode { addr, opcode } => write!(f, "Unknown opcode {:04X} at {:03X}", opcode, addr),
+            Error::StackOverflow { addr } => write!(f, "Stack overflow calling from {:03X}", addr),
+            Error::StackUnderflow { addr } => write!(f, "Stack underflow returning from {:03X}", addr),
+            Error::RomTooLarge { size, available } => write!(f, "ROM is {} bytes but only {} bytes are available", size, available),
+            Error::OutOfBounds { addr } => write!(f, "Memory access out of bounds at {:04X}", addr),
+            Error::WriteProtected { addr } => write!(f, "Write to the interpreter area at {:03X}", addr),
+            Error::InvalidHeader => write!(f, "ROM does not start with a valid header"),
+            Error::InvalidFont { size } => write!(f, "Font is {} bytes, expected 80 or 180", size),
+            Error::BackendMismatch { addr, difference } =>
+                write!(f, "Recompiled block at {:03X} disagrees with the interpreter: {}", addr, difference),
+            Error::MemoryTooLarge { size } => write!(f, "Memory is {} bytes but only 65536 can be addressed", size),
+            Error::FontDoesNotFit { addr, memory_size } =>
+                write!(f, "The font at {:03X} does not fit in {} bytes of memory", addr, memory_size),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,47 @@
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::emulator::display::{HEIGHT, WIDTH};
+use crate::emulator::status_view::Status;
+
+// Everything the renderer needs to draw one screen, copied out of the
+// emulator so that drawing never holds up the CPU.
+pub struct Frame {
+    pub pixels: [[bool; WIDTH]; HEIGHT],
+    pub keys_down: [bool; 16],
+    pub status: Status,
+    pub last_executed: Option<String>,
+    pub message: Option<String>,
+    // The memory panel and the sprite at I, while the debugger is open.
+    pub debugger: Vec<String>,
+    pub halted: Option<String>,
+}
+
+// Double buffer between the CPU and the renderer. The CPU fills in a frame of
+// its own and swaps it in; the renderer takes the newest one whenever it is
+// ready. Frames it never got to are dropped.
+pub struct FrameBuffer {
+    latest: Mutex<Option<Frame>>,
+    ready: Condvar,
+}
+
+impl FrameBuffer {
+    pub fn new() -> FrameBuffer {
+        FrameBuffer {
+            latest: Mutex::new(None),
+            ready: Condvar::new(),
+        }
+    }
+
+    pub fn publish(&self, frame: Frame) {
+        *self.latest.lock().unwrap() = Some(frame);
+        self.ready.notify_one();
+    }
+
+    // Waits up to `timeout` for a frame newer than the last one taken.
+    pub fn wait(&self, timeout: Duration) -> Option<Frame> {
+        let latest = self.latest.lock().unwrap();
+        let (mut latest, _) = self.ready.wait_timeout_while(latest, timeout, |frame| frame.is_none()).unwrap();
+        latest.take()
+    }
+}
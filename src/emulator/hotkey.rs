@@ -0,0 +1,43 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+pub const HELP: &str = "F2 debugger  F3/F4 soft/hard reset  F5 pause  F6 frame advance  F7/F8 speed  F9 save  Esc quit";
+
+// Emulator controls. They live on function and control keys so that they
+// never reach the CHIP-8 keypad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Debugger,
+    Reset,
+    HardReset,
+    Pause,
+    FrameAdvance,
+    Save,
+    SlowDown,
+    SpeedUp,
+    Quit,
+}
+
+impl Hotkey {
+    pub fn from_event(event: &KeyEvent) -> Option<Hotkey> {
+        match event.code {
+            KeyCode::F(2) => Some(Hotkey::Debugger),
+            KeyCode::F(3) => Some(Hotkey::Reset),
+            KeyCode::F(4) => Some(Hotkey::HardReset),
+            KeyCode::F(5) => Some(Hotkey::Pause),
+            KeyCode::F(6) => Some(Hotkey::FrameAdvance),
+            KeyCode::F(7) => Some(Hotkey::SlowDown),
+            KeyCode::F(8) => Some(Hotkey::SpeedUp),
+            KeyCode::F(9) => Some(Hotkey::Save),
+            KeyCode::Esc => Some(Hotkey::Quit),
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => match c.to_ascii_lowercase() {
+                'c' | 'q' => Some(Hotkey::Quit),
+                'd' => Some(Hotkey::Debugger),
+                'r' => Some(Hotkey::Reset),
+                'p' => Some(Hotkey::Pause),
+                's' => Some(Hotkey::Save),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
@@ -1,53 +1,151 @@
-use std::time::Duration;
-use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers, poll};
-use crossterm::terminal;
+use std::time::{Duration, Instant};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
-pub struct Keypad{}
+use crate::emulator::keypad_view;
+
+// The host key for each CHIP-8 key, keeping the shape of the hex keypad on
+// the left of a QWERTY keyboard.
+const HOST_KEYS: [char; 16] = [
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+];
+
+// Long enough to bridge the delay before a terminal starts auto-repeating.
+pub const DEFAULT_HOLD_TIME: Duration = Duration::from_millis(300);
+
+// The state of the 16 CHIP-8 keys, tracked from terminal key events and
+// clicks on the keypad drawn next to the display.
+// Terminals that support the kitty keyboard protocol report releases. Others
+// only send presses and auto-repeats, so there a key counts as held until
+// `hold_time` passes without hearing from it.
+//
+// Events come from the renderer's input queue. Without one, as when running
+// headless, no key is ever down.
+pub struct Keypad {
+    keys: [bool; 16],
+    last_seen: [Instant; 16],
+    hold_time: Duration,
+    reports_releases: bool,
+    // The key held down with the mouse. The terminal always reports mouse
+    // releases.
+    clicked: Option<u8>,
+    // The last key pressed during the current frame, for Fx0A.
+    last_press: Option<u8>,
+}
 
 impl Keypad {
 
-    pub fn pressed() -> Option<u32> {
-        terminal::enable_raw_mode().expect("Could not turn on Raw mode");
-        if let Ok(true) = poll(Duration::from_millis(100)) {
-            let char = match read().unwrap() {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    modifiers: KeyModifiers::NONE,
-                ..}) => Some(c),
-                _ => None
-            };
-    
-            match char {
-                Some('1') => Some(0x1),
-                Some('2') => Some(0x2),
-                Some('3') => Some(0x3),
-                Some('4') => Some(0xc),
-                Some('q') => Some(0x4),
-                Some('w') => Some(0x5),
-                Some('e') => Some(0x6),
-                Some('r') => Some(0xd),
-                Some('a') => Some(0x7),
-                Some('s') => Some(0x8),
-                Some('d') => Some(0x9),
-                Some('f') => Some(0xe),
-                Some('z') => Some(0xa),
-                Some('x') => Some(0x0),
-                Some('c') => Some(0xb),
-                Some('v') => Some(0xf),
-                _ => None
-            }
+    pub fn new() -> Keypad {
+        Keypad {
+            keys: [false; 16],
+            last_seen: [Instant::now(); 16],
+            hold_time: DEFAULT_HOLD_TIME,
+            reports_releases: false,
+            clicked: None,
+            last_press: None,
+        }
+    }
+
+    pub fn set_hold_time(&mut self, hold_time: Duration) {
+        self.hold_time = hold_time;
+    }
+
+    // Forgets the previous frame's key press. Called before the frame's
+    // input is handled.
+    pub fn begin_frame(&mut self) {
+        self.last_press = None;
+    }
+
+    // Updates the key state from a terminal event. Key presses, CHIP-8 keys
+    // or not, are handed back for hotkeys and the memory editor.
+    pub fn handle(&mut self, event: &Event) -> Option<KeyEvent> {
+        match event {
+            Event::Key(key) => {
+                self.track(key);
+                (key.kind != KeyEventKind::Release).then_some(*key)
+            },
+            Event::Mouse(mouse) => {
+                self.track_mouse(mouse);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    pub fn is_down(&self, key: u8) -> bool {
+        if self.clicked == Some(key & 0xf) {
+            return true;
         }
-        else {
-            None
+        let key = key as usize & 0xf;
+        self.keys[key] && (self.reports_releases || self.last_seen[key].elapsed() < self.hold_time)
+    }
+
+    pub fn keys_down(&self) -> [bool; 16] {
+        std::array::from_fn(|key| self.is_down(key as u8))
+    }
+
+    // The key pressed this frame, if any. Auto-repeats count as presses on
+    // terminals that can't tell them apart.
+    pub fn take_press(&mut self) -> Option<u8> {
+        self.last_press.take()
+    }
+
+    // Updates the key state if the event is for a CHIP-8 key.
+    pub fn track(&mut self, event: &KeyEvent) -> bool {
+        let Some(key) = Self::keypad_key(event) else {
+            return false;
+        };
+        let index = key as usize;
+        match event.kind {
+            KeyEventKind::Press => {
+                self.keys[index] = true;
+                self.last_seen[index] = Instant::now();
+                self.last_press = Some(key);
+            },
+            KeyEventKind::Repeat => {
+                self.keys[index] = true;
+                self.last_seen[index] = Instant::now();
+            },
+            KeyEventKind::Release => {
+                self.reports_releases = true;
+                self.keys[index] = false;
+            },
         }
+        true
+    }
 
+    // Letters match whatever the case or Shift state, so Caps Lock doesn't get
+    // in the way. Control and Alt combinations are left for hotkeys. The
+    // arrow keys stand in for 2, 4, 6 and 8, the usual directions.
+    fn keypad_key(event: &KeyEvent) -> Option<u8> {
+        if event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) {
+            return None;
+        }
+        let c = match event.code {
+            KeyCode::Up => return Some(0x2),
+            KeyCode::Left => return Some(0x4),
+            KeyCode::Right => return Some(0x6),
+            KeyCode::Down => return Some(0x8),
+            KeyCode::Char(c) => c.to_ascii_lowercase(),
+            _ => return None,
+        };
+        HOST_KEYS.iter().position(|key| *key == c).map(|key| key as u8)
     }
 
-    fn map_key(c: char) -> Option<u32> {
-        match c {
-            '1'..='f' => c.to_digit(10),
-            _ => None
+    pub fn track_mouse(&mut self, event: &MouseEvent) {
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.clicked = keypad_view::key_at(event.column, event.row);
+                if self.clicked.is_some() {
+                    self.last_press = self.clicked;
+                }
+            },
+            MouseEventKind::Up(MouseButton::Left) => self.clicked = None,
+            _ => {}
         }
     }
 
-}
\ No newline at end of file
+}
+
+pub fn host_key(key: u8) -> char {
+    HOST_KEYS[key as usize & 0xf]
+}
@@ -0,0 +1,54 @@
+use crossterm::style::Stylize;
+
+use crate::emulator::keypad;
+
+// The keypad is drawn to the right of the display and its border, so mouse
+// clicks can be mapped back to keys.
+pub const COLUMN: u16 = 68;
+pub const ROW: u16 = 0;
+// Both include the border on the left or top of the cell.
+const CELL_WIDTH: u16 = 8;
+const CELL_HEIGHT: u16 = 3;
+
+// The COSMAC VIP hex keypad, as laid out on the machine.
+const LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+// Each key shows its hex digit over the host key it is mapped to, and is
+// highlighted while held down.
+pub fn render(keys_down: &[bool; 16]) -> Vec<String> {
+    let mut lines = vec![String::from("┌───────┬───────┬───────┬───────┐")];
+    for (n, row) in LAYOUT.iter().enumerate() {
+        for label in [
+            |key: u8| format!("   {:X}   ", key),
+            |key: u8| format!("  ({})  ", keypad::host_key(key).to_ascii_uppercase()),
+        ] {
+            let cells: Vec<String> = row.iter()
+                .map(|key| match label(*key) {
+                    cell if keys_down[*key as usize] => cell.reverse().to_string(),
+                    cell => cell,
+                })
+                .collect();
+            lines.push(format!("│{}│", cells.join("│")));
+        }
+        lines.push(String::from(if n < 3 {
+            "├───────┼───────┼───────┼───────┤"
+        } else {
+            "└───────┴───────┴───────┴───────┘"
+        }));
+    }
+    lines
+}
+
+// The key under a terminal cell, if any. Borders don't belong to a key.
+pub fn key_at(column: u16, row: u16) -> Option<u8> {
+    let (x, y) = (column.checked_sub(COLUMN)?, row.checked_sub(ROW)?);
+    if x % CELL_WIDTH == 0 || y % CELL_HEIGHT == 0 {
+        return None;
+    }
+    LAYOUT.get((y / CELL_HEIGHT) as usize)?.get((x / CELL_WIDTH) as usize).copied()
+}
@@ -0,0 +1,162 @@
+use crate::emulator::error::Error;
+use crate::emulator::Opcode;
+
+pub const CHIP8_MEMORY_SIZE: usize = 0x1000;
+pub const XOCHIP_MEMORY_SIZE: usize = 0x10000;
+// Where the original interpreter lived. ROMs are loaded after it.
+pub const INTERPRETER_END: usize = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Fetch,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: usize,
+    pub value: u8,
+}
+
+pub type Hook = Box<dyn FnMut(&Access) + Send>;
+
+// Program memory. Accesses made by the program are bounds checked, can be
+// kept out of the interpreter area and are reported to any subscribed hooks.
+// Loading and poking are done by the host and skip protection and hooks.
+pub struct Memory {
+    bytes: Vec<u8>,
+    protect_interpreter: bool,
+    hooks: Vec<Hook>,
+    // The instruction decoded at each address the last time it was fetched.
+    // Every kind of write drops the entries covering the changed byte, so
+    // self-modifying code still runs what it wrote.
+    decoded: Vec<Option<(u16, Opcode)>>,
+    // Counts the writes that changed an instruction already fetched, so
+    // anything built from fetched code can tell when to throw it away.
+    code_writes: u64,
+}
+
+impl Memory {
+    pub fn new(size: usize, protect_interpreter: bool) -> Memory {
+        Memory {
+            bytes: vec![0; size],
+            protect_interpreter,
+            hooks: Vec::new(),
+            decoded: vec![None; size],
+            code_writes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn subscribe(&mut self, hook: Hook) {
+        self.hooks.push(hook);
+    }
+
+    pub fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    pub fn code_writes(&self) -> u64 {
+        self.code_writes
+    }
+
+    pub fn load(&mut self, addr: usize, data: &[u8]) -> Result<(), Error> {
+        if addr > self.bytes.len() {
+            return Err(Error::OutOfBounds { addr });
+        }
+        let available = self.bytes.len().saturating_sub(addr);
+        if data.len() > available {
+            return Err(Error::RomTooLarge { size: data.len(), available });
+        }
+        self.bytes[addr..addr + data.len()].copy_from_slice(data);
+        self.decoded[addr.saturating_sub(1)..addr + data.len()].fill(None);
+        self.code_writes += 1;
+        Ok(())
+    }
+
+    // Zeroes every byte, keeping the hooks.
+    pub fn clear(&mut self) {
+        self.bytes.fill(0);
+        self.decoded.fill(None);
+        self.code_writes += 1;
+    }
+
+    pub fn poke(&mut self, addr: usize, value: u8) {
+        self.bytes[addr] = value;
+        self.invalidate(addr);
+    }
+
+    pub fn fetch(&mut self, addr: usize) -> Result<u16, Error> {
+        if addr + 1 >= self.bytes.len() {
+            return Err(Error::OutOfBounds { addr });
+        }
+        let word = (self.bytes[addr] as u16) << 8 | self.bytes[addr + 1] as u16;
+        self.notify(AccessKind::Fetch, addr, self.bytes[addr]);
+        Ok(word)
+    }
+
+    // Like `fetch`, but also hands back the decoded instruction, decoding it
+    // only if the word has changed since it was last fetched.
+    pub fn fetch_decoded(&mut self, addr: usize, decode: fn(u16) -> Opcode) -> Result<(u16, Opcode), Error> {
+        if let Some(Some(decoded)) = self.decoded.get(addr).copied() {
+            self.notify(AccessKind::Fetch, addr, self.bytes[addr]);
+            return Ok(decoded);
+        }
+        let word = self.fetch(addr)?;
+        let decoded = (word, decode(word));
+        self.decoded[addr] = Some(decoded);
+        Ok(decoded)
+    }
+
+    pub fn read(&mut self, addr: usize) -> Result<u8, Error> {
+        let value = *self.bytes.get(addr).ok_or(Error::OutOfBounds { addr })?;
+        self.notify(AccessKind::Read, addr, value);
+        Ok(value)
+    }
+
+    pub fn write(&mut self, addr: usize, value: u8) -> Result<(), Error> {
+        if addr >= self.bytes.len() {
+            return Err(Error::OutOfBounds { addr });
+        }
+        if self.protect_interpreter && addr < INTERPRETER_END {
+            return Err(Error::WriteProtected { addr });
+        }
+        self.bytes[addr] = value;
+        self.invalidate(addr);
+        self.notify(AccessKind::Write, addr, value);
+        Ok(())
+    }
+
+    // Instructions are two bytes, so the one starting just before `addr`
+    // changes too.
+    fn invalidate(&mut self, addr: usize) {
+        let first = addr.saturating_sub(1);
+        if self.decoded[first..=addr].iter().any(Option::is_some) {
+            self.decoded[first..=addr].fill(None);
+            self.code_writes += 1;
+        }
+    }
+
+    fn notify(&mut self, kind: AccessKind, addr: usize, value: u8) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        let access = Access { kind, addr, value };
+        for hook in &mut self.hooks {
+            hook(&access);
+        }
+    }
+}
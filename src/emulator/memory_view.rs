@@ -0,0 +1,103 @@
+use crossterm::event::KeyCode;
+use crossterm::style::Stylize;
+use std::ops::Range;
+
+const BYTES_PER_ROW: u16 = 16;
+const ROWS: u16 = 16;
+
+// Hex dump of emulator memory for the terminal front end. While running the
+// view follows the PC; while paused a cursor can be moved around and bytes
+// are edited by typing two hex digits.
+pub struct MemoryView {
+    pub visible: bool,
+    cursor: u16,
+    high_nibble: Option<u8>,
+    font_area: Range<u16>,
+}
+
+impl MemoryView {
+    pub fn new(font_area: Range<u16>) -> MemoryView {
+        MemoryView {
+            visible: false,
+            cursor: 0x200,
+            high_nibble: None,
+            font_area,
+        }
+    }
+
+    pub fn follow(&mut self, addr: u16) {
+        self.cursor = addr;
+        self.high_nibble = None;
+    }
+
+    // Returns the address and new value of a byte once both of its digits
+    // have been typed.
+    pub fn handle_key(&mut self, key: KeyCode, memory: &[u8], pc: u16, i: u16) -> Option<(u16, u8)> {
+        let last = (memory.len() - 1) as u16;
+        match key {
+            KeyCode::Left => self.follow(self.cursor.saturating_sub(1)),
+            KeyCode::Right => self.follow(self.cursor.saturating_add(1).min(last)),
+            KeyCode::Up => self.follow(self.cursor.saturating_sub(BYTES_PER_ROW)),
+            KeyCode::Down => self.follow(self.cursor.saturating_add(BYTES_PER_ROW).min(last)),
+            KeyCode::PageUp => self.follow(self.cursor.saturating_sub(BYTES_PER_ROW * ROWS)),
+            KeyCode::PageDown => self.follow(self.cursor.saturating_add(BYTES_PER_ROW * ROWS).min(last)),
+            KeyCode::Char('p') => self.follow(pc.min(last)),
+            KeyCode::Char('i') => self.follow(i.min(last)),
+            KeyCode::Char(c) => {
+                let digit = c.to_digit(16)? as u8;
+                match self.high_nibble.take() {
+                    None => self.high_nibble = Some(digit),
+                    Some(high) => {
+                        let addr = self.cursor;
+                        self.follow(self.cursor.saturating_add(1).min(last));
+                        return Some((addr, high << 4 | digit));
+                    }
+                }
+            },
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, memory: &[u8], pc: u16, i: u16, recent_writes: &[u16], editing: bool) -> Vec<String> {
+        let rows = (memory.len() / BYTES_PER_ROW as usize) as u16;
+        let top = (self.cursor / BYTES_PER_ROW).saturating_sub(ROWS / 2).min(rows.saturating_sub(ROWS));
+
+        let mut lines = Vec::new();
+        for row in top..(top + ROWS).min(rows) {
+            // In usize, since the last row of 64K memory ends past FFFF.
+            let start = row as usize * BYTES_PER_ROW as usize;
+            let end = start + BYTES_PER_ROW as usize;
+            let mut line = format!("{:04X} ", start);
+            for (addr, byte) in (start..end).map(|addr| (addr as u16, memory[addr])) {
+                let text = match self.high_nibble {
+                    Some(high) if editing && addr == self.cursor => format!("{:X}_", high),
+                    _ => format!("{:02X}", byte),
+                };
+                let styled = if editing && addr == self.cursor {
+                    text.reverse()
+                } else if addr == pc || addr == pc.wrapping_add(1) {
+                    text.black().on_yellow()
+                } else if addr == i {
+                    text.white().on_blue()
+                } else if recent_writes.contains(&addr) {
+                    text.red()
+                } else if self.font_area.contains(&addr) {
+                    text.cyan()
+                } else {
+                    text.stylize()
+                };
+                line.push_str(&format!(" {}", styled));
+            }
+            line.push_str("  ");
+            for byte in &memory[start..end] {
+                line.push(if byte.is_ascii_graphic() { *byte as char } else { '.' });
+            }
+            lines.push(line);
+        }
+        lines.push(format!("{} PC  {} I  {} font  {} written{}",
+            "  ".on_yellow(), "  ".on_blue(), "..".cyan(), "..".red(),
+            if editing { "   arrows/PgUp/PgDn move, p/i jump to PC/I, hex digits edit" } else { "" }));
+        lines
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::emulator::Opcode;
+
+// Counts executions per address and per opcode variant, and the inclusive
+// number of cycles spent inside every subroutine. The active call path is
+// followed through Call/Return so samples can be written out as folded stacks
+// for flamegraph.pl or inferno.
+pub struct Profiler {
+    by_address: HashMap<u16, (u64, Opcode)>,
+    by_opcode: HashMap<&'static str, u64>,
+    by_call_target: HashMap<u16, u64>,
+    folded: HashMap<Vec<u16>, u64>,
+    frames: Vec<u16>,
+    total: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            by_address: HashMap::new(),
+            by_opcode: HashMap::new(),
+            by_call_target: HashMap::new(),
+            folded: HashMap::new(),
+            frames: Vec::new(),
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, opcode: Opcode) {
+        self.total += 1;
+        self.by_address.entry(pc).or_insert((0, opcode)).0 += 1;
+        *self.by_opcode.entry(opcode.name()).or_insert(0) += 1;
+
+        // Recursive calls only count once towards their target.
+        for (depth, target) in self.frames.iter().enumerate() {
+            if !self.frames[..depth].contains(target) {
+                *self.by_call_target.entry(*target).or_insert(0) += 1;
+            }
+        }
+
+        match self.folded.get_mut(&self.frames[..]) {
+            Some(count) => *count += 1,
+            None => { self.folded.insert(self.frames.clone(), 1); }
+        }
+    }
+
+    pub fn enter_call(&mut self, target: u16) {
+        self.frames.push(target);
+    }
+
+    pub fn leave_call(&mut self) {
+        self.frames.pop();
+    }
+
+    // Forgets the call path when the stack is thrown away. The counts stay.
+    pub fn reset_calls(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn write_report(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Total instructions: {}", self.total)?;
+
+        writeln!(out)?;
+        writeln!(out, "Hot spots by address")?;
+        let mut addresses: Vec<_> = self.by_address.iter().collect();
+        addresses.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.0.cmp(b.0)));
+        for (pc, (count, opcode)) in addresses {
+            writeln!(out, "  {:04X}  {:>12}  {:>6.2}%  {}", pc, count, self.percent(*count), opcode)?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Opcodes")?;
+        let mut opcodes: Vec<_> = self.by_opcode.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, count) in opcodes {
+            writeln!(out, "  {:<20}  {:>12}  {:>6.2}%", name, count, self.percent(*count))?;
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Subroutines (inclusive)")?;
+        let mut targets: Vec<_> = self.by_call_target.iter().collect();
+        targets.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (target, count) in targets {
+            writeln!(out, "  {:04X}  {:>12}  {:>6.2}%", target, count, self.percent(*count))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_folded(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.folded.iter()
+            .map(|(frames, count)| {
+                let mut stack = String::from("main");
+                for target in frames {
+                    stack.push_str(&format!(";sub_{:03X}", target));
+                }
+                (stack, count)
+            })
+            .collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            writeln!(out, "{} {}", stack, count)?;
+        }
+        Ok(())
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total.max(1) as f64
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::sync::Arc;
+
+use crate::emulator::config::Config;
+use crate::emulator::display::{HEIGHT, WIDTH};
+use crate::emulator::error::Error;
+use crate::emulator::font::SMALL_FONT_SIZE;
+use crate::emulator::memory::Memory;
+use crate::emulator::tracer::Tracer;
+use crate::emulator::{Emulator, Opcode};
+
+// Long enough for any real straight-line run, short enough that translating
+// code that only runs once stays cheap.
+const MAX_BLOCK_LENGTH: usize = 64;
+
+// One instruction with its registers unpacked and the quirks that apply to
+// it already decided.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MicroOp {
+    Load { x: usize, value: u8 },
+    AddImmediate { x: usize, value: u8 },
+    Copy { x: usize, y: usize },
+    Or { x: usize, y: usize, reset_vf: bool },
+    And { x: usize, y: usize, reset_vf: bool },
+    Xor { x: usize, y: usize, reset_vf: bool },
+    Add { x: usize, y: usize },
+    Subtract { x: usize, y: usize },
+    SubtractReversed { x: usize, y: usize },
+    // Vx = Vy shifted, where y is x unless the shift quirk is on.
+    ShiftRight { x: usize, y: usize },
+    ShiftLeft { x: usize, y: usize },
+    LoadI(u16),
+    AddI { x: usize },
+    // I = base + digit * size, for either font.
+    FontDigit { x: usize, base: u16, size: u16 },
+    LoadDelay { x: usize },
+    SetDelay { x: usize },
+    SetSound { x: usize },
+    // Drawing and memory access go back through `Emulator::execute`.
+    Execute(Opcode),
+}
+
+impl MicroOp {
+    // Anything that can jump, skip, wait for a key, halt or use the random
+    // number generator is left to `Emulator::step`, and so ends a block.
+    fn translate(opcode: Opcode, config: &Config) -> Option<MicroOp> {
+        let reset_vf = config.quirks.vf_reset;
+        let shift_source = |x: u16, y: u16| if config.quirks.shift_vy { y as usize } else { x as usize };
+        let op = match opcode {
+            Opcode::Set(x, value) => MicroOp::Load { x: x as usize, value },
+            Opcode::IncrementReg(x, value) => MicroOp::AddImmediate { x: x as usize, value },
+            Opcode::CopyReg(x, y) => MicroOp::Copy { x: x as usize, y: y as usize },
+            Opcode::BitwiseOr(x, y) => MicroOp::Or { x: x as usize, y: y as usize, reset_vf },
+            Opcode::BitwiseAnd(x, y) => MicroOp::And { x: x as usize, y: y as usize, reset_vf },
+            Opcode::BitwiseXor(x, y) => MicroOp::Xor { x: x as usize, y: y as usize, reset_vf },
+            Opcode::AddReg(x, y) => MicroOp::Add { x: x as usize, y: y as usize },
+            Opcode::SubtractReg(x, y) => MicroOp::Subtract { x: x as usize, y: y as usize },
+            Opcode::NegativeSubtractReg(x, y) => MicroOp::SubtractReversed { x: x as usize, y: y as usize },
+            Opcode::BitwiseRight(x, y) => MicroOp::ShiftRight { x: x as usize, y: shift_source(x, y) },
+            Opcode::BitwiseLeft(x, y) => MicroOp::ShiftLeft { x: x as usize, y: shift_source(x, y) },
+            Opcode::SetI(addr) => MicroOp::LoadI(addr),
+            Opcode::AddI(x) => MicroOp::AddI { x: x as usize },
+            Opcode::SetIToFontDigit(x) => MicroOp::FontDigit { x: x as usize, base: config.font_address, size: 5 },
+            Opcode::SetIToBigFontDigit(x) => MicroOp::FontDigit {
+                x: x as usize,
+                base: config.font_address + SMALL_FONT_SIZE as u16,
+                size: 10,
+            },
+            Opcode::CopyDelayToReg(x) => MicroOp::LoadDelay { x: x as usize },
+            Opcode::SetDelayFromReg(x) => MicroOp::SetDelay { x: x as usize },
+            Opcode::SetSoundFromReg(x) => MicroOp::SetSound { x: x as usize },
+            Opcode::ClearScreen
+            | Opcode::Draw(..)
+            | Opcode::BinaryCodeI(_)
+            | Opcode::CopyRegistersToI(_)
+            | Opcode::CopyIToRegisters(_) => MicroOp::Execute(opcode),
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+pub struct Instruction {
+    pub pc: u16,
+    pub raw: u16,
+    pub opcode: Opcode,
+    pub op: MicroOp,
+}
+
+// A straight run of instructions, translated from the first one up to the
+// next one that has to go through `Emulator::step`.
+pub struct Block {
+    pub start: u16,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Block {
+    pub fn translate(memory: &mut Memory, start: u16, config: &Config) -> Block {
+        let mut instructions = Vec::new();
+        let mut addr = start as usize;
+        // The last word of a full 64K memory is left to `step`, which stops
+        // the program if it runs past it.
+        while instructions.len() < MAX_BLOCK_LENGTH && addr + 1 < memory.len() && addr < u16::MAX as usize - 1 {
+            let Ok((raw, opcode)) = memory.fetch_decoded(addr, Emulator::decode) else {
+                break;
+            };
+            let Some(op) = MicroOp::translate(opcode, config) else {
+                break;
+            };
+            instructions.push(Instruction { pc: addr as u16, raw, opcode, op });
+            addr += 2;
+            // A draw can end the frame and a store can overwrite the rest of
+            // the block, so either is the last instruction.
+            if matches!(opcode, Opcode::Draw(..) | Opcode::BinaryCodeI(_) | Opcode::CopyRegistersToI(_)) {
+                break;
+            }
+        }
+        Block { start, instructions }
+    }
+}
+
+// Translated blocks by start address. Every block is thrown away as soon as
+// memory reports a write over code that has been fetched.
+pub struct BlockCache {
+    blocks: HashMap<u16, Arc<Block>>,
+    code_writes: u64,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache {
+            blocks: HashMap::new(),
+            code_writes: 0,
+        }
+    }
+
+    // The block starting at `pc`, translated on first use. None when the
+    // instruction there has to go through `Emulator::step`.
+    pub fn get(&mut self, memory: &mut Memory, pc: u16, config: &Config) -> Option<Arc<Block>> {
+        if memory.code_writes() != self.code_writes {
+            self.blocks.clear();
+            self.code_writes = memory.code_writes();
+        }
+        let block = self.blocks.entry(pc)
+            .or_insert_with(|| Arc::new(Block::translate(memory, pc, config)));
+        (!block.instructions.is_empty()).then(|| block.clone())
+    }
+}
+
+impl Emulator {
+    // Runs the first `count` instructions of a block. The tracer, profiler
+    // and cycle count see them exactly as if `step` had run them.
+    pub(super) fn run_block(&mut self, block: &Block, count: usize) -> Result<(), Error> {
+        for instruction in &block.instructions[..count] {
+            self.pc = instruction.pc + 2;
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(instruction.pc, instruction.opcode);
+            }
+            if let Err(error) = self.execute_micro_op(instruction.op) {
+                self.pc = instruction.pc;
+                return Err(error);
+            }
+            self.retire(instruction.pc, instruction.raw, instruction.opcode);
+        }
+        Ok(())
+    }
+
+    // Runs the same instructions through `step` first, with the tracer and
+    // profiler put aside, then rewinds and runs the block. Any difference in
+    // the result or the machine state afterwards is reported as an error.
+    pub(super) fn run_block_checked(&mut self, block: &Block, count: usize) -> Result<(), Error> {
+        let before = Snapshot::of(self);
+        let tracer = mem::replace(&mut self.tracer, Tracer::new(0));
+        let profiler = self.profiler.take();
+        let expected = (0..count).try_for_each(|_| self.step().map(|_| ()));
+        self.tracer = tracer;
+        self.profiler = profiler;
+        let expected_state = Snapshot::of(self);
+        before.restore(self);
+
+        let result = self.run_block(block, count);
+        let difference = if result != expected {
+            Some(format!("got {:?}, expected {:?}", result, expected))
+        } else {
+            Snapshot::of(self).difference(&expected_state)
+        };
+        match difference {
+            Some(difference) => Err(Error::BackendMismatch { addr: block.start, difference }),
+            None => result,
+        }
+    }
+
+    fn execute_micro_op(&mut self, op: MicroOp) -> Result<(), Error> {
+        let v = &mut self.v;
+        match op {
+            MicroOp::Load { x, value } => v[x] = value,
+            MicroOp::AddImmediate { x, value } => v[x] = v[x].wrapping_add(value),
+            MicroOp::Copy { x, y } => v[x] = v[y],
+            MicroOp::Or { x, y, reset_vf } => {
+                v[x] |= v[y];
+                if reset_vf {
+                    v[15] = 0;
+                }
+            },
+            MicroOp::And { x, y, reset_vf } => {
+                v[x] &= v[y];
+                if reset_vf {
+                    v[15] = 0;
+                }
+            },
+            MicroOp::Xor { x, y, reset_vf } => {
+                v[x] ^= v[y];
+                if reset_vf {
+                    v[15] = 0;
+                }
+            },
+            MicroOp::Add { x, y } => {
+                let (sum, carry) = v[x].overflowing_add(v[y]);
+                v[x] = sum;
+                v[15] = carry as u8;
+            },
+            MicroOp::Subtract { x, y } => {
+                let (difference, borrow) = v[x].overflowing_sub(v[y]);
+                v[x] = difference;
+                v[15] = !borrow as u8;
+            },
+            MicroOp::SubtractReversed { x, y } => {
+                let (difference, borrow) = v[y].overflowing_sub(v[x]);
+                v[x] = difference;
+                v[15] = !borrow as u8;
+            },
+            MicroOp::ShiftRight { x, y } => {
+                let value = v[y];
+                v[x] = value >> 1;
+                v[15] = value & 1;
+            },
+            MicroOp::ShiftLeft { x, y } => {
+                let value = v[y];
+                v[x] = value << 1;
+                v[15] = value >> 7;
+            },
+            MicroOp::LoadI(addr) => self.i = addr,
+            MicroOp::AddI { x } => self.i = self.i.wrapping_add(v[x] as u16),
+            MicroOp::FontDigit { x, base, size } => self.i = base + (v[x] & 0xf) as u16 * size,
+            MicroOp::LoadDelay { x } => v[x] = self.delay_timer,
+            MicroOp::SetDelay { x } => self.delay_timer = v[x],
+            MicroOp::SetSound { x } => self.sound_timer = v[x],
+            MicroOp::Execute(opcode) => return self.execute(opcode),
+        }
+        Ok(())
+    }
+}
+
+// Everything a block can change, for the differential mode to compare and
+// rewind.
+struct Snapshot {
+    v: [u8; 16],
+    i: u16,
+    pc: u16,
+    delay_timer: u8,
+    sound_timer: u8,
+    cycle: u64,
+    memory: Vec<u8>,
+    pixels: [[bool; WIDTH]; HEIGHT],
+    draw_flag: bool,
+    waiting_for_vblank: bool,
+    recent_writes: VecDeque<u16>,
+}
+
+impl Snapshot {
+    fn of(emu: &Emulator) -> Snapshot {
+        Snapshot {
+            v: emu.v,
+            i: emu.i,
+            pc: emu.pc,
+            delay_timer: emu.delay_timer,
+            sound_timer: emu.sound_timer,
+            cycle: emu.cycle,
+            memory: emu.memory.as_slice().to_vec(),
+            pixels: emu.display.pixels,
+            draw_flag: emu.draw_flag,
+            waiting_for_vblank: emu.waiting_for_vblank,
+            recent_writes: emu.recent_writes.clone(),
+        }
+    }
+
+    // Only bytes that changed are written back, so blocks are only thrown
+    // away if the code itself was overwritten.
+    fn restore(self, emu: &mut Emulator) {
+        emu.v = self.v;
+        emu.i = self.i;
+        emu.pc = self.pc;
+        emu.delay_timer = self.delay_timer;
+        emu.sound_timer = self.sound_timer;
+        emu.cycle = self.cycle;
+        let changed: Vec<usize> = (0..self.memory.len())
+            .filter(|&addr| emu.memory.as_slice()[addr] != self.memory[addr])
+            .collect();
+        for addr in changed {
+            emu.memory.poke(addr, self.memory[addr]);
+        }
+        emu.display.pixels = self.pixels;
+        emu.draw_flag = self.draw_flag;
+        emu.waiting_for_vblank = self.waiting_for_vblank;
+        emu.recent_writes = self.recent_writes;
+    }
+
+    fn difference(&self, expected: &Snapshot) -> Option<String> {
+        if let Some(x) = (0..16).find(|&x| self.v[x] != expected.v[x]) {
+            return Some(format!("V{:X} is {:02X}, expected {:02X}", x, self.v[x], expected.v[x]));
+        }
+        let registers = [
+            ("I", self.i as u64, expected.i as u64),
+            ("PC", self.pc as u64, expected.pc as u64),
+            ("DT", self.delay_timer as u64, expected.delay_timer as u64),
+            ("ST", self.sound_timer as u64, expected.sound_timer as u64),
+            ("cycle", self.cycle, expected.cycle),
+        ];
+        if let Some((name, value, expected)) = registers.into_iter().find(|(_, value, expected)| value != expected) {
+            return Some(format!("{} is {:X}, expected {:X}", name, value, expected));
+        }
+        if let Some(addr) = (0..self.memory.len()).find(|&addr| self.memory[addr] != expected.memory[addr]) {
+            return Some(format!("memory at {:03X} is {:02X}, expected {:02X}", addr, self.memory[addr], expected.memory[addr]));
+        }
+        if self.pixels != expected.pixels {
+            return Some(String::from("the display differs"));
+        }
+        if (self.draw_flag, self.waiting_for_vblank) != (expected.draw_flag, expected.waiting_for_vblank) {
+            return Some(String::from("the draw flags differ"));
+        }
+        if self.recent_writes != expected.recent_writes {
+            return Some(String::from("the recent writes differ"));
+        }
+        None
+    }
+}
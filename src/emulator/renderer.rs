@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use crossterm::event::{
+    poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{execute, terminal};
+
+use crate::emulator::frame::{Frame, FrameBuffer};
+use crate::emulator::hotkey::HELP;
+use crate::emulator::keypad_view;
+use crate::emulator::status_view;
+
+// How long the renderer waits for a frame before checking for input again.
+const INPUT_INTERVAL: Duration = Duration::from_millis(4);
+
+// Raw mode, mouse reporting and, where the terminal supports them, press,
+// repeat and release events. Everything is put back when this is dropped,
+// including on a panic.
+pub struct Terminal {
+    enhanced: bool,
+}
+
+impl Terminal {
+    pub fn open() -> Terminal {
+        terminal::enable_raw_mode().expect("Could not turn on Raw mode");
+        let _ = execute!(io::stdout(), EnableMouseCapture);
+        let mut enhanced = false;
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            enhanced = execute!(io::stdout(), PushKeyboardEnhancementFlags(flags)).is_ok();
+        }
+        Terminal { enhanced }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(io::stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(io::stdout(), DisableMouseCapture);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// The renderer's side of the emulator: forwards key and mouse events to the
+// CPU's input queue and draws the newest frame, until the CPU is done.
+pub fn run(frames: &FrameBuffer, input: Sender<Event>, cpu_done: impl Fn() -> bool) {
+    loop {
+        let done = cpu_done();
+        if let Some(frame) = frames.wait(INPUT_INTERVAL) {
+            draw(&frame);
+        }
+        if done {
+            return;
+        }
+        while let Ok(true) = poll(Duration::ZERO) {
+            match read() {
+                Ok(event @ (Event::Key(_) | Event::Mouse(_))) => {
+                    if input.send(event).is_err() {
+                        return;
+                    }
+                },
+                Ok(_) => {},
+                Err(_) => return,
+            }
+        }
+    }
+}
+
+fn draw(frame: &Frame) {
+    print!("{}", render(frame));
+    io::stdout().flush().expect("Unable to write to terminal");
+}
+
+// The terminal is in raw mode, so lines end in "\r\n" and the whole screen
+// is written in one go.
+pub fn render(frame: &Frame) -> String {
+    let mut screen = String::new();
+    screen.push_str(&format!("{}[2J{}[H", 27 as char, 27 as char));
+    let width = frame.pixels[0].len();
+    let mut display = vec![format!("╔{:═<1$}╗", "", width)];
+    for row in frame.pixels {
+        let pixels: String = row.iter().map(|pixel| if *pixel { '▉' } else { ' ' }).collect();
+        display.push(format!("║{}║", pixels));
+    }
+    display.push(format!("╚{:═<1$}╝", "", width));
+
+    // The keypad goes to the right, where keypad_view expects clicks.
+    let keypad = keypad_view::render(&frame.keys_down);
+    for (n, line) in display.iter().enumerate() {
+        screen.push_str(line);
+        if let Some(keys) = keypad.get(n) {
+            screen.push_str("  ");
+            screen.push_str(keys);
+        }
+        screen.push_str("\r\n");
+    }
+    screen.push_str("\r\n");
+    if let Some(last) = &frame.last_executed {
+        screen.push_str(&format!("Last executed: {}\r\n", last));
+    }
+    for line in status_view::render(&frame.status) {
+        screen.push_str(&line);
+        screen.push_str("\r\n");
+    }
+    screen.push_str(HELP);
+    screen.push_str("\r\n");
+    if let Some(message) = &frame.message {
+        screen.push_str(message);
+        screen.push_str("\r\n");
+    }
+    if !frame.debugger.is_empty() {
+        screen.push_str("\r\n");
+        for line in &frame.debugger {
+            screen.push_str(line);
+            screen.push_str("\r\n");
+        }
+    }
+    if let Some(halted) = &frame.halted {
+        screen.push_str(halted);
+        screen.push_str("\r\n");
+    }
+    screen
+}
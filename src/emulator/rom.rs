@@ -0,0 +1,31 @@
+use crate::emulator::error::Error;
+
+pub const HEADER_MAGIC: &[u8; 4] = b"C8HD";
+pub const HEADER_SIZE: usize = 8;
+
+// A program together with where it goes in memory and where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub load_address: u16,
+    pub entry_point: u16,
+}
+
+impl Rom {
+    // Reads a ROM that starts with an explicit header:
+    //
+    //   0  "C8HD"
+    //   4  load address, big endian
+    //   6  entry point, big endian
+    //   8  program
+    pub fn with_header(bytes: &[u8]) -> Result<Rom, Error> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != HEADER_MAGIC {
+            return Err(Error::InvalidHeader);
+        }
+        Ok(Rom {
+            data: bytes[HEADER_SIZE..].to_vec(),
+            load_address: u16::from_be_bytes([bytes[4], bytes[5]]),
+            entry_point: u16::from_be_bytes([bytes[6], bytes[7]]),
+        })
+    }
+}
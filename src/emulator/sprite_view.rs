@@ -0,0 +1,39 @@
+const ON: char = '█';
+const OFF: char = '·';
+
+// Renders sprite data the way Dxyn would draw it: one byte per 8 pixel row,
+// or two bytes per row for a 16x16 SCHIP sprite (Dxy0).
+pub fn render_sprite(bytes: &[u8], wide: bool) -> Vec<String> {
+    let bytes_per_row = if wide { 2 } else { 1 };
+    bytes.chunks(bytes_per_row)
+        .map(|row| row.iter().map(|byte| render_byte(*byte)).collect())
+        .collect()
+}
+
+// Lays out memory from `start` to `end` as a sheet of 8 pixel wide sprites,
+// `height` rows each, with the address of every sprite above it.
+pub fn render_sheet(memory: &[u8], start: usize, end: usize, height: usize, columns: usize) -> Vec<String> {
+    let end = end.min(memory.len());
+    let height = height.max(1);
+    let addresses: Vec<usize> = (start..end).step_by(height).collect();
+
+    let mut lines = Vec::new();
+    for group in addresses.chunks(columns.max(1)) {
+        lines.push(group.iter().map(|addr| format!("{:<8}", format!("{:03X}", addr))).collect::<Vec<_>>().join("    ").trim_end().to_string());
+        for row in 0..height {
+            let line: Vec<String> = group.iter()
+                .map(|addr| match addr + row {
+                    a if a < end => render_byte(memory[a]),
+                    _ => " ".repeat(8),
+                })
+                .collect();
+            lines.push(line.join("    "));
+        }
+        lines.push(String::new());
+    }
+    lines
+}
+
+fn render_byte(byte: u8) -> String {
+    (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { ON } else { OFF }).collect()
+}
@@ -0,0 +1,52 @@
+use crate::emulator::error::Error;
+
+pub const VIP_STACK_SIZE: usize = 12;
+pub const SCHIP_STACK_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CallFrame {
+    // Address of the 2nnn instruction that made the call.
+    pub call_site: u16,
+    pub target: u16,
+    pub return_address: u16,
+}
+
+// Call stack with a fixed number of entries, like the memory the original
+// interpreters set aside for it.
+pub struct Stack {
+    frames: Box<[CallFrame]>,
+    depth: usize,
+}
+
+impl Stack {
+    pub fn new(size: usize) -> Stack {
+        let empty = CallFrame { call_site: 0, target: 0, return_address: 0 };
+        Stack {
+            frames: vec![empty; size].into_boxed_slice(),
+            depth: 0,
+        }
+    }
+
+    pub fn push(&mut self, frame: CallFrame) -> Result<(), Error> {
+        if self.depth == self.frames.len() {
+            return Err(Error::StackOverflow { addr: frame.call_site });
+        }
+        self.frames[self.depth] = frame;
+        self.depth += 1;
+        Ok(())
+    }
+
+    // `addr` is the address of the return instruction, for the error.
+    pub fn pop(&mut self, addr: u16) -> Result<CallFrame, Error> {
+        if self.depth == 0 {
+            return Err(Error::StackUnderflow { addr });
+        }
+        self.depth -= 1;
+        Ok(self.frames[self.depth])
+    }
+
+    // The active calls, outermost first.
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames[..self.depth]
+    }
+}
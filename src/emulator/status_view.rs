@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+pub struct Status {
+    pub rom_name: String,
+    pub fps: f64,
+    pub speed: String,
+    pub instructions_per_frame: u32,
+    pub paused: bool,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+}
+
+// Everything about the running program that isn't the display or keypad:
+// what is loaded, how fast it runs, the timers and the registers.
+pub fn render(status: &Status) -> Vec<String> {
+    vec![
+        format!("ROM   {}", status.rom_name),
+        format!("FPS   {:<5.1} IPF {:<4} Speed {}{}", status.fps, status.instructions_per_frame, status.speed,
+            if status.paused { "  PAUSED" } else { "" }),
+        format!("DT {:02X}  ST {:02X}  {}", status.delay_timer, status.sound_timer,
+            if status.sound_timer > 0 { "♪ beep" } else { "" }),
+        format!("PC {:03X}  I {:03X}", status.pc, status.i),
+        format!("V0-7  {}", hex_bytes(&status.v[..8])),
+        format!("V8-F  {}", hex_bytes(&status.v[8..])),
+    ]
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+// Frames per second, averaged over the last second or so.
+pub struct FpsCounter {
+    since: Instant,
+    frames: u32,
+    fps: f64,
+}
+
+impl FpsCounter {
+    pub fn new() -> FpsCounter {
+        FpsCounter {
+            since: Instant::now(),
+            frames: 0,
+            fps: 0.0,
+        }
+    }
+
+    // Returns whether there is a new reading to show.
+    pub fn tick(&mut self, frames: u32) -> bool {
+        self.frames += frames;
+        let elapsed = self.since.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return false;
+        }
+        self.fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.frames = 0;
+        self.since = Instant::now();
+        true
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
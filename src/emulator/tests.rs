@@ -0,0 +1,1000 @@
+// Table-driven opcode tests. Each case sets up an emulator with the
+// instruction at 0x200, runs exactly one step through fetch/decode/execute
+// and then checks the state it lists. Anything not listed is not checked.
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use crossterm::style::Stylize;
+
+use crate::emulator::{Emulator, Halt, Opcode};
+use crate::emulator::config::{Backend, Config, IndexIncrement, IndexOverflow, Quirks, UnknownOpcodePolicy};
+use crate::emulator::error::Error;
+use crate::emulator::font::{Font, SCHIP_BIG};
+use crate::emulator::frame::FrameBuffer;
+use crate::emulator::hotkey::Hotkey;
+use crate::emulator::keypad_view;
+use crate::emulator::memory::{Access, AccessKind};
+use crate::emulator::memory_view::MemoryView;
+use crate::emulator::recompiler::MicroOp;
+use crate::emulator::rom::Rom;
+use crate::emulator::stack::CallFrame;
+use crate::emulator::status_view::{self, Status};
+use crate::emulator::tracer::Tracer;
+
+enum Expect {
+    V(usize, u8),
+    I(u16),
+    Pc(u16),
+    Memory(u16, Vec<u8>),
+    Stack(Vec<u16>),
+    DelayTimer(u8),
+    SoundTimer(u8),
+    Pixel(usize, usize, bool),
+}
+
+struct OpcodeTest {
+    name: &'static str,
+    opcode: u16,
+    config: Config,
+    v: Vec<(usize, u8)>,
+    i: u16,
+    memory: Vec<(u16, Vec<u8>)>,
+    stack: Vec<u16>,
+    delay_timer: u8,
+    pixels: Vec<(usize, usize)>,
+    expect: Vec<Expect>,
+}
+
+impl OpcodeTest {
+    fn new(name: &'static str, opcode: u16) -> OpcodeTest {
+        OpcodeTest {
+            name,
+            opcode,
+            config: Config::default(),
+            v: Vec::new(),
+            i: 0,
+            memory: Vec::new(),
+            stack: Vec::new(),
+            delay_timer: 0,
+            pixels: Vec::new(),
+            expect: Vec::new(),
+        }
+    }
+
+    fn quirks(mut self, quirks: Quirks) -> Self {
+        self.config.quirks = quirks;
+        self
+    }
+
+    fn v(mut self, reg: usize, val: u8) -> Self {
+        self.v.push((reg, val));
+        self
+    }
+
+    fn i(mut self, i: u16) -> Self {
+        self.i = i;
+        self
+    }
+
+    fn memory(mut self, addr: u16, bytes: &[u8]) -> Self {
+        self.memory.push((addr, bytes.to_vec()));
+        self
+    }
+
+    fn stack(mut self, frames: &[u16]) -> Self {
+        self.stack = frames.to_vec();
+        self
+    }
+
+    fn delay_timer(mut self, val: u8) -> Self {
+        self.delay_timer = val;
+        self
+    }
+
+    fn pixel(mut self, x: usize, y: usize) -> Self {
+        self.pixels.push((x, y));
+        self
+    }
+
+    fn expect_v(mut self, reg: usize, val: u8) -> Self {
+        self.expect.push(Expect::V(reg, val));
+        self
+    }
+
+    fn expect_i(mut self, i: u16) -> Self {
+        self.expect.push(Expect::I(i));
+        self
+    }
+
+    fn expect_pc(mut self, pc: u16) -> Self {
+        self.expect.push(Expect::Pc(pc));
+        self
+    }
+
+    fn expect_memory(mut self, addr: u16, bytes: &[u8]) -> Self {
+        self.expect.push(Expect::Memory(addr, bytes.to_vec()));
+        self
+    }
+
+    fn expect_stack(mut self, frames: &[u16]) -> Self {
+        self.expect.push(Expect::Stack(frames.to_vec()));
+        self
+    }
+
+    fn expect_delay_timer(mut self, val: u8) -> Self {
+        self.expect.push(Expect::DelayTimer(val));
+        self
+    }
+
+    fn expect_sound_timer(mut self, val: u8) -> Self {
+        self.expect.push(Expect::SoundTimer(val));
+        self
+    }
+
+    fn expect_pixel(mut self, x: usize, y: usize, on: bool) -> Self {
+        self.expect.push(Expect::Pixel(x, y, on));
+        self
+    }
+
+    fn setup(&self) -> Emulator {
+        let mut emu = Emulator::with_config(self.config).unwrap();
+        emu.load(self.opcode.to_be_bytes().to_vec()).unwrap();
+        for (reg, val) in &self.v {
+            emu.v[*reg] = *val;
+        }
+        emu.i = self.i;
+        for (addr, bytes) in &self.memory {
+            for (offset, byte) in bytes.iter().enumerate() {
+                emu.memory.poke(*addr as usize + offset, *byte);
+            }
+        }
+        for return_address in &self.stack {
+            let frame = CallFrame { call_site: return_address - 2, target: 0, return_address: *return_address };
+            emu.push_stack(frame).expect("stack set up too deep");
+        }
+        emu.delay_timer = self.delay_timer;
+        for (x, y) in &self.pixels {
+            emu.display.pixels[*y][*x] = true;
+        }
+        emu
+    }
+
+    // Returns a description of every expectation that did not hold.
+    fn run(&self) -> Vec<String> {
+        let mut emu = self.setup();
+        emu.step().expect("step failed");
+
+        let mut failures = Vec::new();
+        let mut check = |what: String, actual: String, expected: String| {
+            if actual != expected {
+                failures.push(format!("{} ({:04X}): {} is {}, expected {}", self.name, self.opcode, what, actual, expected));
+            }
+        };
+        for expect in &self.expect {
+            match expect {
+                Expect::V(reg, val) => check(format!("V{:X}", reg), format!("{:02X}", emu.v[*reg]), format!("{:02X}", val)),
+                Expect::I(i) => check("I".into(), format!("{:03X}", emu.i), format!("{:03X}", i)),
+                Expect::Pc(pc) => check("PC".into(), format!("{:03X}", emu.pc), format!("{:03X}", pc)),
+                Expect::Memory(addr, bytes) => {
+                    let actual = &emu.memory()[*addr as usize..*addr as usize + bytes.len()];
+                    check(format!("memory at {:03X}", addr), format!("{:02X?}", actual), format!("{:02X?}", bytes));
+                },
+                Expect::Stack(frames) => {
+                    let actual: Vec<u16> = emu.call_stack().iter().map(|frame| frame.return_address).collect();
+                    check("stack".into(), format!("{:03X?}", actual), format!("{:03X?}", frames));
+                },
+                Expect::DelayTimer(val) => check("DT".into(), emu.delay_timer.to_string(), val.to_string()),
+                Expect::SoundTimer(val) => check("ST".into(), emu.sound_timer.to_string(), val.to_string()),
+                Expect::Pixel(x, y, on) => check(format!("pixel ({}, {})", x, y), emu.display.pixels[*y][*x].to_string(), on.to_string()),
+            }
+        }
+        failures
+    }
+}
+
+fn run_table(cases: Vec<OpcodeTest>) {
+    let failures: Vec<String> = cases.iter().flat_map(|case| case.run()).collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn flow_control() {
+    run_table(vec![
+        OpcodeTest::new("CLS", 0x00e0).pixel(3, 4).expect_pixel(3, 4, false).expect_pc(0x202),
+        OpcodeTest::new("RET", 0x00ee).stack(&[0x208, 0x346]).expect_pc(0x346).expect_stack(&[0x208]),
+        OpcodeTest::new("JP", 0x1abc).expect_pc(0xabc),
+        OpcodeTest::new("CALL", 0x2abc).stack(&[0x208]).expect_pc(0xabc).expect_stack(&[0x208, 0x202]),
+        OpcodeTest::new("JP V0", 0xb300).v(0, 0x12).expect_pc(0x312),
+        OpcodeTest::new("SE byte, equal", 0x3312).v(3, 0x12).expect_pc(0x204),
+        OpcodeTest::new("SE byte, not equal", 0x3312).v(3, 0x13).expect_pc(0x202),
+        OpcodeTest::new("SNE byte, equal", 0x4312).v(3, 0x12).expect_pc(0x202),
+        OpcodeTest::new("SNE byte, not equal", 0x4312).v(3, 0x13).expect_pc(0x204),
+        OpcodeTest::new("SE reg, equal", 0x5340).v(3, 0x12).v(4, 0x12).expect_pc(0x204),
+        OpcodeTest::new("SE reg, not equal", 0x5340).v(3, 0x12).v(4, 0x13).expect_pc(0x202),
+        OpcodeTest::new("SNE reg, equal", 0x9340).v(3, 0x12).v(4, 0x12).expect_pc(0x202),
+        OpcodeTest::new("SNE reg, not equal", 0x9340).v(3, 0x12).v(4, 0x13).expect_pc(0x204),
+    ]);
+}
+
+#[test]
+fn register_loads() {
+    run_table(vec![
+        OpcodeTest::new("LD byte", 0x6a42).expect_v(0xa, 0x42),
+        OpcodeTest::new("ADD byte", 0x7a02).v(0xa, 0x40).expect_v(0xa, 0x42),
+        OpcodeTest::new("ADD byte wraps without touching VF", 0x7a02).v(0xa, 0xff).expect_v(0xa, 0x01).expect_v(0xf, 0),
+        OpcodeTest::new("LD reg", 0x8ab0).v(0xb, 0x42).expect_v(0xa, 0x42).expect_v(0xb, 0x42),
+        OpcodeTest::new("RND with zero mask", 0xca00).v(0xa, 0x42).expect_v(0xa, 0),
+        OpcodeTest::new("LD I", 0xa123).expect_i(0x123),
+        OpcodeTest::new("ADD I", 0xf31e).i(0x300).v(3, 0x20).expect_i(0x320),
+        OpcodeTest::new("ADD I wraps", 0xf31e).i(0xfff0).v(3, 0x20).expect_i(0x10),
+        OpcodeTest::new("LD F", 0xf329).v(3, 0).expect_i(0x50),
+        OpcodeTest::new("LD F steps 5 bytes per digit", 0xf329).v(3, 0xb).expect_i(0x87),
+        OpcodeTest::new("LD F uses the low nibble", 0xf329).v(3, 0x12).expect_i(0x5a),
+        OpcodeTest::new("LD HF", 0xf330).v(3, 2).expect_i(0xb4),
+    ]);
+}
+
+#[test]
+fn logic_and_arithmetic() {
+    run_table(vec![
+        OpcodeTest::new("OR", 0x8121).v(1, 0xf0).v(2, 0x0f).expect_v(1, 0xff),
+        OpcodeTest::new("AND", 0x8122).v(1, 0xf0).v(2, 0x3c).expect_v(1, 0x30),
+        OpcodeTest::new("XOR", 0x8123).v(1, 0xf0).v(2, 0x3c).expect_v(1, 0xcc),
+        OpcodeTest::new("ADD reg", 0x8124).v(1, 0x10).v(2, 0x20).expect_v(1, 0x30).expect_v(0xf, 0),
+        OpcodeTest::new("ADD reg with carry", 0x8124).v(1, 0xff).v(2, 0x02).expect_v(1, 0x01).expect_v(0xf, 1),
+        OpcodeTest::new("ADD reg to exactly 256", 0x8124).v(1, 0x80).v(2, 0x80).expect_v(1, 0x00).expect_v(0xf, 1),
+        OpcodeTest::new("SUB", 0x8125).v(1, 0x30).v(2, 0x10).expect_v(1, 0x20).expect_v(0xf, 1),
+        OpcodeTest::new("SUB with borrow", 0x8125).v(1, 0x10).v(2, 0x30).expect_v(1, 0xe0).expect_v(0xf, 0),
+        OpcodeTest::new("SUBN", 0x8127).v(1, 0x10).v(2, 0x30).expect_v(1, 0x20).expect_v(0xf, 1),
+        OpcodeTest::new("SUBN with borrow", 0x8127).v(1, 0x30).v(2, 0x10).expect_v(1, 0xe0).expect_v(0xf, 0),
+        OpcodeTest::new("SHR", 0x8126).v(1, 0x81).expect_v(1, 0x40).expect_v(0xf, 1),
+        OpcodeTest::new("SHR even", 0x8126).v(1, 0x80).v(0xf, 1).expect_v(1, 0x40).expect_v(0xf, 0),
+        OpcodeTest::new("SHL", 0x812e).v(1, 0x81).expect_v(1, 0x02).expect_v(0xf, 1),
+        OpcodeTest::new("SHL small", 0x812e).v(1, 0x41).v(0xf, 1).expect_v(1, 0x82).expect_v(0xf, 0),
+        OpcodeTest::new("SHR ignores Vy", 0x8126).v(1, 0x04).v(2, 0x81).expect_v(1, 0x02).expect_v(2, 0x81).expect_v(0xf, 0),
+        OpcodeTest::new("SHR VIP", 0x8126).quirks(Quirks::cosmac_vip()).v(1, 0x04).v(2, 0x81)
+            .expect_v(1, 0x40).expect_v(2, 0x81).expect_v(0xf, 1),
+        OpcodeTest::new("SHL VIP", 0x812e).quirks(Quirks::cosmac_vip()).v(1, 0x04).v(2, 0x81)
+            .expect_v(1, 0x02).expect_v(2, 0x81).expect_v(0xf, 1),
+        OpcodeTest::new("SHR VIP into VF", 0x8f16).quirks(Quirks::cosmac_vip()).v(0xf, 0x80).v(1, 0x03)
+            .expect_v(0xf, 1),
+    ]);
+}
+
+#[test]
+fn flags() {
+    run_table(vec![
+        OpcodeTest::new("ADD reg clears VF", 0x8124).v(1, 0x10).v(2, 0x20).v(0xf, 1).expect_v(1, 0x30).expect_v(0xf, 0),
+        OpcodeTest::new("SUB equal", 0x8125).v(1, 0x30).v(2, 0x30).expect_v(1, 0).expect_v(0xf, 1),
+        OpcodeTest::new("SUBN equal", 0x8127).v(1, 0x30).v(2, 0x30).expect_v(1, 0).expect_v(0xf, 1),
+        OpcodeTest::new("ADD into VF", 0x8f14).v(0xf, 0xff).v(1, 0x02).expect_v(0xf, 1),
+        OpcodeTest::new("ADD from VF", 0x81f4).v(1, 0x02).v(0xf, 0xff).expect_v(1, 0x01).expect_v(0xf, 1),
+        OpcodeTest::new("SUB into VF", 0x8f15).v(0xf, 0x30).v(1, 0x10).expect_v(0xf, 1),
+        OpcodeTest::new("SUBN into VF", 0x8f17).v(0xf, 0x30).v(1, 0x10).expect_v(0xf, 0),
+        OpcodeTest::new("SHR into VF", 0x8f06).v(0xf, 0x02).expect_v(0xf, 0),
+        OpcodeTest::new("SHL into VF", 0x8f0e).v(0xf, 0x81).expect_v(0xf, 1),
+        OpcodeTest::new("OR keeps VF", 0x8121).v(1, 0xf0).v(2, 0x0f).v(0xf, 1).expect_v(0xf, 1),
+        OpcodeTest::new("OR VIP", 0x8121).quirks(Quirks::cosmac_vip()).v(1, 0xf0).v(2, 0x0f).v(0xf, 1)
+            .expect_v(1, 0xff).expect_v(0xf, 0),
+        OpcodeTest::new("AND VIP", 0x8122).quirks(Quirks::cosmac_vip()).v(1, 0xf0).v(2, 0x3c).v(0xf, 1)
+            .expect_v(1, 0x30).expect_v(0xf, 0),
+        OpcodeTest::new("XOR VIP", 0x8123).quirks(Quirks::cosmac_vip()).v(1, 0xf0).v(2, 0x3c).v(0xf, 1)
+            .expect_v(1, 0xcc).expect_v(0xf, 0),
+    ]);
+}
+
+#[test]
+fn memory() {
+    run_table(vec![
+        OpcodeTest::new("LD B", 0xf333).i(0x300).v(3, 123).expect_memory(0x300, &[1, 2, 3]),
+        OpcodeTest::new("LD B zero padded", 0xf333).i(0x300).v(3, 7).expect_memory(0x300, &[0, 0, 7]),
+        OpcodeTest::new("LD [I]", 0xf255).i(0x300).v(0, 1).v(1, 2).v(2, 3).v(3, 4)
+            .expect_memory(0x300, &[1, 2, 3, 0]),
+        OpcodeTest::new("LD V, [I]", 0xf265).i(0x300).memory(0x300, &[1, 2, 3, 4])
+            .expect_v(0, 1).expect_v(1, 2).expect_v(2, 3).expect_v(3, 0),
+        OpcodeTest::new("LD [I] leaves I", 0xf255).i(0x300).expect_i(0x300),
+        OpcodeTest::new("LD [I] CHIP-48 increment", 0xf255).i(0x300)
+            .quirks(Quirks { index_increment: IndexIncrement::ByX, ..Quirks::default() }).expect_i(0x302),
+        OpcodeTest::new("LD V, [I] VIP increment", 0xf265).i(0x300).quirks(Quirks::cosmac_vip()).expect_i(0x303),
+        OpcodeTest::new("LD B wraps", 0xf333).i(0xfff).v(3, 123).expect_memory(0xfff, &[1]).expect_memory(0, &[2, 3]),
+        OpcodeTest::new("LD [I] wraps", 0xf155).i(0xfff).v(0, 1).v(1, 2).expect_memory(0xfff, &[1]).expect_memory(0, &[2]),
+        OpcodeTest::new("LD V, [I] wraps", 0xf165).i(0xfff).memory(0xfff, &[1]).memory(0, &[2])
+            .expect_v(0, 1).expect_v(1, 2),
+    ]);
+}
+
+#[test]
+fn timers() {
+    run_table(vec![
+        OpcodeTest::new("LD V, DT", 0xf307).delay_timer(42).expect_v(3, 42),
+        OpcodeTest::new("LD DT, V", 0xf315).v(3, 42).expect_delay_timer(42),
+        OpcodeTest::new("LD ST, V", 0xf318).v(3, 42).expect_sound_timer(42),
+    ]);
+}
+
+#[test]
+fn display() {
+    run_table(vec![
+        OpcodeTest::new("DRW", 0xd121).v(1, 2).v(2, 3).i(0x300).memory(0x300, &[0xc0])
+            .expect_pixel(2, 3, true).expect_pixel(3, 3, true).expect_pixel(4, 3, false).expect_v(0xf, 0),
+        OpcodeTest::new("DRW collision", 0xd121).v(1, 2).v(2, 3).i(0x300).memory(0x300, &[0xc0]).pixel(3, 3)
+            .expect_pixel(2, 3, true).expect_pixel(3, 3, false).expect_v(0xf, 1),
+        OpcodeTest::new("DRW start wraps", 0xd121).v(1, 66).v(2, 35).i(0x300).memory(0x300, &[0x80])
+            .expect_pixel(2, 3, true),
+        OpcodeTest::new("DRW clears VF", 0xd121).v(1, 2).v(2, 3).v(0xf, 1).i(0x300).memory(0x300, &[0xc0])
+            .expect_v(0xf, 0),
+        OpcodeTest::new("DRW clips right edge", 0xd121).v(1, 62).v(2, 0).i(0x300).memory(0x300, &[0xf0])
+            .expect_pixel(63, 0, true).expect_pixel(0, 0, false).expect_pixel(1, 0, false),
+        OpcodeTest::new("DRW clips bottom edge", 0xd122).v(1, 0).v(2, 31).i(0x300).memory(0x300, &[0x80, 0x80])
+            .expect_pixel(0, 31, true).expect_pixel(0, 0, false),
+        OpcodeTest::new("DRW wraps right edge", 0xd121).v(1, 62).v(2, 0).i(0x300).memory(0x300, &[0xf0])
+            .quirks(Quirks { wrap_sprites: true, ..Quirks::default() })
+            .expect_pixel(63, 0, true).expect_pixel(0, 0, true).expect_pixel(1, 0, true).expect_pixel(2, 0, false),
+        OpcodeTest::new("DRW wraps bottom edge", 0xd122).v(1, 0).v(2, 31).i(0x300).memory(0x300, &[0x80, 0x80])
+            .quirks(Quirks { wrap_sprites: true, ..Quirks::default() })
+            .expect_pixel(0, 31, true).expect_pixel(0, 0, true),
+        OpcodeTest::new("DRW counts collided rows", 0xd123).v(1, 0).v(2, 0).i(0x300).memory(0x300, &[0x80, 0x80, 0x80])
+            .pixel(0, 0).pixel(0, 2).quirks(Quirks { collision_rows: true, ..Quirks::default() })
+            .expect_v(0xf, 2),
+        OpcodeTest::new("DRW SCHIP counts collided rows", 0xd122).v(1, 0).v(2, 0).i(0x300).memory(0x300, &[0x80, 0x80])
+            .pixel(0, 0).pixel(0, 1).quirks(Quirks::schip()).expect_v(0xf, 2),
+        OpcodeTest::new("DRW reads past the end of memory from the start", 0xd122).v(1, 0).v(2, 0).i(0xfff)
+            .memory(0xfff, &[0x80]).memory(0x000, &[0x40])
+            .expect_pixel(0, 0, true).expect_pixel(1, 1, true).expect_pixel(0, 1, false),
+    ]);
+}
+
+// The keypad is not attached, so no key is ever down.
+#[test]
+fn keys() {
+    run_table(vec![
+        OpcodeTest::new("SKP", 0xe39e).v(3, 1).expect_pc(0x202),
+        OpcodeTest::new("SKNP", 0xe3a1).v(3, 1).expect_pc(0x204),
+        OpcodeTest::new("LD K waits", 0xf30a).v(3, 7).expect_pc(0x200).expect_v(3, 7),
+    ]);
+}
+
+fn key_event(c: char, kind: KeyEventKind) -> KeyEvent {
+    KeyEvent::new_with_kind(KeyCode::Char(c), KeyModifiers::NONE, kind)
+}
+
+#[test]
+fn key_mapping() {
+    let mut emu = Emulator::new();
+    let mut press = |code, modifiers| {
+        emu.keypad.track(&KeyEvent::new(code, modifiers));
+        emu.keypad.take_press()
+    };
+    assert_eq!(press(KeyCode::Char('w'), KeyModifiers::NONE), Some(0x5));
+    assert_eq!(press(KeyCode::Char('W'), KeyModifiers::NONE), Some(0x5));
+    assert_eq!(press(KeyCode::Char('W'), KeyModifiers::SHIFT), Some(0x5));
+    assert_eq!(press(KeyCode::Char('w'), KeyModifiers::CONTROL), None);
+    assert_eq!(press(KeyCode::Up, KeyModifiers::NONE), Some(0x2));
+    assert_eq!(press(KeyCode::Right, KeyModifiers::SHIFT), Some(0x6));
+    assert_eq!(press(KeyCode::Esc, KeyModifiers::NONE), None);
+
+    let hotkey = |code, modifiers| Hotkey::from_event(&KeyEvent::new(code, modifiers));
+    assert_eq!(hotkey(KeyCode::F(5), KeyModifiers::NONE), Some(Hotkey::Pause));
+    assert_eq!(hotkey(KeyCode::Esc, KeyModifiers::NONE), Some(Hotkey::Quit));
+    assert_eq!(hotkey(KeyCode::Char('C'), KeyModifiers::CONTROL | KeyModifiers::SHIFT), Some(Hotkey::Quit));
+    assert_eq!(hotkey(KeyCode::Char('r'), KeyModifiers::NONE), None);
+}
+
+#[test]
+fn held_keys() {
+    // SKP V0 in a loop, with V0 = 5 (the W key)
+    let rom = vec![0x60, 0x05, 0xe0, 0x9e, 0x12, 0x02, 0x12, 0x06];
+    let mut emu = Emulator::new();
+    emu.load(rom).unwrap();
+    emu.step().unwrap();
+
+    assert!(emu.keypad.track(&key_event('w', KeyEventKind::Press)));
+    assert!(!emu.keypad.track(&key_event('p', KeyEventKind::Press)));
+    assert_eq!(emu.keypad.take_press(), Some(5));
+    emu.step().unwrap();
+    assert_eq!(emu.pc, 0x206);
+
+    // Without release events the key is let go once the hold time passes.
+    emu.pc = 0x202;
+    emu.set_key_hold_time(Duration::ZERO);
+    emu.step().unwrap();
+    assert_eq!(emu.pc, 0x204);
+
+    // Once the terminal has reported a release, keys stay down until theirs.
+    emu.set_key_hold_time(Duration::ZERO);
+    emu.keypad.track(&key_event('q', KeyEventKind::Release));
+    emu.keypad.track(&key_event('w', KeyEventKind::Press));
+    emu.pc = 0x202;
+    emu.step().unwrap();
+    assert_eq!(emu.pc, 0x206);
+    emu.keypad.track(&key_event('w', KeyEventKind::Release));
+    emu.pc = 0x202;
+    emu.step().unwrap();
+    assert_eq!(emu.pc, 0x204);
+}
+
+#[test]
+fn decoding_is_strict() {
+    let cases = [
+        (0x0123, Opcode::Sys(0x123)),
+        (0x00e1, Opcode::Sys(0x0e1)),
+        (0x5120, Opcode::SkipRegEqual(1, 2)),
+        (0x5121, Opcode::Unknown(0x5121)),
+        (0x9121, Opcode::Unknown(0x9121)),
+        (0x8128, Opcode::Unknown(0x8128)),
+        (0xe39e, Opcode::SkipKeyPressed(3)),
+        (0xe3a1, Opcode::SkipKeyNotPressed(3)),
+        (0xe3a0, Opcode::Unknown(0xe3a0)),
+        (0xe19f, Opcode::Unknown(0xe19f)),
+        (0xf3ff, Opcode::Unknown(0xf3ff)),
+    ];
+    for (word, opcode) in cases {
+        assert_eq!(Emulator::decode(word), opcode, "decoding {:04X}", word);
+    }
+}
+
+fn run_unknown(config: Config) -> (Emulator, Result<Option<Halt>, Error>) {
+    let mut emu = Emulator::with_config(config).unwrap();
+    emu.load(vec![0x51, 0x21, 0x60, 0x01]).unwrap();
+    let result = emu.step();
+    (emu, result)
+}
+
+#[test]
+fn unknown_opcode_policies() {
+    let (emu, result) = run_unknown(Config { unknown_opcode: UnknownOpcodePolicy::Trap, ..Config::default() });
+    assert_eq!(result, Err(Error::UnknownOpcode { addr: 0x200, opcode: 0x5121 }));
+    assert_eq!(emu.pc, 0x200);
+
+    let (emu, result) = run_unknown(Config { unknown_opcode: UnknownOpcodePolicy::Halt, ..Config::default() });
+    assert_eq!(result, Ok(Some(Halt::UnknownOpcode { addr: 0x200, opcode: 0x5121 })));
+    assert_eq!(emu.pc, 0x200);
+
+    let (emu, result) = run_unknown(Config { unknown_opcode: UnknownOpcodePolicy::Skip, ..Config::default() });
+    assert_eq!(result, Ok(None));
+    assert_eq!(emu.pc, 0x202);
+}
+
+#[test]
+fn sys_calls() {
+    let mut emu = Emulator::new();
+    emu.load(vec![0x01, 0x23]).unwrap();
+    assert_eq!(emu.step(), Err(Error::UnknownOpcode { addr: 0x200, opcode: 0x0123 }));
+
+    let mut emu = Emulator::with_config(Config { sys_noop: true, ..Config::default() }).unwrap();
+    emu.load(vec![0x01, 0x23]).unwrap();
+    assert_eq!(emu.step(), Ok(None));
+    assert_eq!(emu.pc, 0x202);
+}
+
+#[test]
+fn halts() {
+    let mut emu = Emulator::new();
+    emu.load(vec![0x00, 0xfd]).unwrap();
+    assert_eq!(emu.step(), Ok(Some(Halt::Exit)));
+    assert_eq!(emu.pc, 0x200);
+
+    let mut emu = Emulator::new();
+    emu.load(vec![0x60, 0x01, 0x12, 0x02]).unwrap();
+    assert_eq!(emu.run_frames(10), Ok(Some(Halt::InfiniteLoop(0x202))));
+    assert_eq!(emu.pc, 0x202);
+
+    let mut emu = Emulator::with_config(Config { sys_noop: true, ..Config::default() }).unwrap();
+    emu.load(vec![0x1f, 0xfe]).unwrap();
+    assert_eq!(emu.run_frames(10), Ok(Some(Halt::EndOfMemory)));
+}
+
+#[test]
+fn stack_limits() {
+    let mut emu = Emulator::with_config(Config { stack_size: 2, ..Config::default() }).unwrap();
+    emu.load(vec![0x22, 0x02, 0x22, 0x04, 0x22, 0x06]).unwrap();
+    assert_eq!(emu.step(), Ok(None));
+    assert_eq!(emu.step(), Ok(None));
+    assert_eq!(emu.step(), Err(Error::StackOverflow { addr: 0x204 }));
+    assert_eq!(emu.pc, 0x204);
+    assert_eq!(emu.call_stack(), &[
+        CallFrame { call_site: 0x200, target: 0x202, return_address: 0x202 },
+        CallFrame { call_site: 0x202, target: 0x204, return_address: 0x204 },
+    ]);
+
+    let mut emu = Emulator::new();
+    emu.load(vec![0x00, 0xee]).unwrap();
+    assert_eq!(emu.step(), Err(Error::StackUnderflow { addr: 0x200 }));
+}
+
+#[test]
+fn memory_bounds() {
+    let mut emu = Emulator::new();
+    assert_eq!(emu.load(vec![0; 0xe00]), Ok(()));
+    assert_eq!(emu.load(vec![0; 0xe01]), Err(Error::RomTooLarge { size: 0xe01, available: 0xe00 }));
+
+    let mut emu = Emulator::with_config(Config { memory_size: 0x10000, ..Config::default() }).unwrap();
+    assert_eq!(emu.load(vec![0; 0xfe00]), Ok(()));
+
+    // Running on past the last word of 64K, and skipping past it. Each
+    // backend stops with PC on the last instruction that ran.
+    for backend in [Backend::Interpreter, Backend::Recompiler, Backend::Differential] {
+        let config = Config { memory_size: 0x10000, sys_noop: true, backend, ..Config::default() };
+        let mut emu = Emulator::with_config(config).unwrap();
+        emu.set_instructions_per_frame(1000);
+        emu.load(vec![0; 0xfe00]).unwrap();
+        assert_eq!(emu.run_frames(100), Ok(Some(Halt::EndOfMemory)));
+        assert_eq!(emu.pc, 0xfffe);
+
+        // SE V0, 00 in the second to last word
+        let mut emu = Emulator::with_config(config).unwrap();
+        emu.set_instructions_per_frame(1000);
+        emu.load(vec![0; 0xfe00]).unwrap();
+        emu.write_memory(0xfffc, 0x30).unwrap();
+        assert_eq!(emu.run_frames(100), Ok(Some(Halt::EndOfMemory)));
+        assert_eq!(emu.pc, 0xfffc);
+    }
+
+    assert_eq!(Emulator::with_config(Config { memory_size: 0x10001, ..Config::default() }).err(),
+        Some(Error::MemoryTooLarge { size: 0x10001 }));
+    assert_eq!(Emulator::with_config(Config { memory_size: 128, ..Config::default() }).err(),
+        Some(Error::FontDoesNotFit { addr: 0x50, memory_size: 128 }));
+
+    // LD B, V0 with I at the last byte of memory
+    let mut emu = Emulator::with_config(Config { index_overflow: IndexOverflow::Error, ..Config::default() }).unwrap();
+    emu.load(vec![0xaf, 0xff, 0xf0, 0x33]).unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.step(), Err(Error::OutOfBounds { addr: 0x1000 }));
+
+    // DRW V0, V0, 2 with I at the last byte of memory
+    let mut emu = Emulator::with_config(Config { index_overflow: IndexOverflow::Error, ..Config::default() }).unwrap();
+    emu.load(vec![0xaf, 0xff, 0xd0, 0x02]).unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.step(), Err(Error::OutOfBounds { addr: 0x1000 }));
+
+    // Debugger writes past the end of memory
+    assert_eq!(emu.write_memory(0x1000, 0), Err(Error::OutOfBounds { addr: 0x1000 }));
+    assert_eq!(emu.write_memory(0xfff, 0), Ok(()));
+}
+
+#[test]
+fn memory_view_end_of_memory() {
+    let memory = vec![0x41; 0x10000];
+    let mut view = MemoryView::new(0x50..0xf0);
+    assert_eq!(view.handle_key(KeyCode::Char('p'), &memory, 0xfffe, 0), None);
+    let lines = view.render(&memory, 0xfffe, 0, &[], true);
+    assert!(lines[15].starts_with("FFF0 "));
+    assert!(lines[15].ends_with("AAAAAAAAAAAAAAAA"));
+
+    // Jumping to an I past the end of 4K memory stops at the last byte.
+    let memory = vec![0; 0x1000];
+    assert_eq!(view.handle_key(KeyCode::Char('i'), &memory, 0x200, 0xffff), None);
+    assert_eq!(view.handle_key(KeyCode::Char('1'), &memory, 0x200, 0xffff), None);
+    assert_eq!(view.handle_key(KeyCode::Char('2'), &memory, 0x200, 0xffff), Some((0xfff, 0x12)));
+}
+
+#[test]
+fn interpreter_area_protection() {
+    let rom = vec![0x60, 0x07, 0xa1, 0xff, 0xf0, 0x55];
+
+    let mut emu = Emulator::new();
+    emu.load(rom.clone()).unwrap();
+    for _ in 0..3 {
+        emu.step().unwrap();
+    }
+    assert_eq!(emu.memory()[0x1ff], 7);
+
+    let mut emu = Emulator::with_config(Config { protect_interpreter: true, ..Config::default() }).unwrap();
+    emu.load(rom).unwrap();
+    emu.step().unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.step(), Err(Error::WriteProtected { addr: 0x1ff }));
+    assert_eq!(emu.memory()[0x1ff], 0);
+}
+
+#[test]
+fn memory_hooks() {
+    let accesses = Arc::new(Mutex::new(Vec::new()));
+    let mut emu = Emulator::new();
+    emu.load(vec![0xa3, 0x00, 0x60, 0x42, 0xf0, 0x55]).unwrap();
+    let log = accesses.clone();
+    emu.subscribe_memory(Box::new(move |access: &Access| {
+        if access.kind != AccessKind::Fetch {
+            log.lock().unwrap().push(*access);
+        }
+    }));
+    for _ in 0..3 {
+        emu.step().unwrap();
+    }
+    assert_eq!(*accesses.lock().unwrap(), vec![Access { kind: AccessKind::Write, addr: 0x300, value: 0x42 }]);
+}
+
+#[test]
+fn self_modifying_code() {
+    let mut emu = Emulator::new();
+    emu.load(vec![
+        0x72, 0x01, // ADD V2, 01, rewritten to ADD V3, 05
+        0xa2, 0x00, 0x60, 0x73, 0x61, 0x05, 0xf1, 0x55,
+        0x12, 0x00,
+    ]).unwrap();
+    let fetches = Arc::new(Mutex::new(0));
+    let count = fetches.clone();
+    emu.subscribe_memory(Box::new(move |access: &Access| {
+        if access.kind == AccessKind::Fetch && access.addr == 0x200 {
+            *count.lock().unwrap() += 1;
+        }
+    }));
+    for _ in 0..12 {
+        emu.step().unwrap();
+    }
+    assert_eq!((emu.v[2], emu.v[3]), (1, 5));
+
+    // Changed from the debugger, only the second byte.
+    emu.write_memory(0x201, 0x07).unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.v[3], 12);
+    assert_eq!(*fetches.lock().unwrap(), 3);
+
+    // Every instruction is cached now, so fetching them again decodes
+    // nothing. The loop's store drops the first one once more.
+    static DECODES: AtomicU64 = AtomicU64::new(0);
+    fn counting_decode(word: u16) -> Opcode {
+        DECODES.fetch_add(1, Ordering::Relaxed);
+        Emulator::decode(word)
+    }
+    let fetch_all = |emu: &mut Emulator| {
+        for addr in (0x200..0x20c).step_by(2) {
+            assert_eq!(emu.memory.fetch_decoded(addr, counting_decode).unwrap().0, emu.memory.fetch(addr).unwrap());
+        }
+    };
+    fetch_all(&mut emu);
+    assert_eq!(DECODES.load(Ordering::Relaxed), 0);
+    for _ in 0..5 {
+        emu.step().unwrap();
+    }
+    fetch_all(&mut emu);
+    assert_eq!(DECODES.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn recompiled_blocks() {
+    let mut emu = Emulator::with_config(Config { backend: Backend::Recompiler, ..Config::cosmac_vip() }).unwrap();
+    emu.load(vec![
+        0x60, 0x05, 0x81, 0x01, 0xa3, 0x00, 0xd0, 0x15, // up to and including the draw
+        0x70, 0x01, 0x12, 0x08,                         // up to the jump
+    ]).unwrap();
+    let block = emu.block_at_pc().unwrap();
+    assert_eq!(block.instructions.len(), 4);
+    assert_eq!(block.instructions[1].op, MicroOp::Or { x: 1, y: 0, reset_vf: true });
+    assert_eq!(block.instructions[3].op, MicroOp::Execute(Opcode::Draw(0, 1, 5)));
+    emu.pc = 0x208;
+    assert_eq!(emu.block_at_pc().unwrap().instructions.len(), 1);
+    emu.pc = 0x20a;
+    assert!(emu.block_at_pc().is_none());
+
+    // The self-modifying loop from above gives the same results however it
+    // is run.
+    let run = |backend| {
+        let mut emu = Emulator::with_config(Config { backend, ..Config::default() }).unwrap();
+        emu.load(vec![0x72, 0x01, 0xa2, 0x00, 0x60, 0x73, 0x61, 0x05, 0xf1, 0x55, 0x12, 0x00]).unwrap();
+        emu.run_frames(3).unwrap();
+        (emu.v, emu.pc, emu.cycle, emu.memory()[0x200..0x20c].to_vec())
+    };
+    let expected = run(Backend::Interpreter);
+    assert_eq!(expected.0[3], 25);
+    assert_eq!(run(Backend::Recompiler), expected);
+    assert_eq!(run(Backend::Differential), expected);
+
+    // ADD I wraps around in blocks too.
+    let mut emu = Emulator::with_config(Config { backend: Backend::Recompiler, ..Config::default() }).unwrap();
+    emu.load(vec![0x60, 0xff, 0xf0, 0x1e, 0x12, 0x00]).unwrap();
+    emu.set_instructions_per_frame(1000);
+    emu.run_frame().unwrap();
+    assert_eq!(emu.i, (333u32 * 0xff % 0x10000) as u16);
+
+    // LD V0, 81; LD V1, 04; SHR V1, V0; LD V2, 04; SHL V2, V0 shift V0 with
+    // the VIP quirks and V1 and V2 in place without.
+    let rom = vec![0x60, 0x81, 0x61, 0x04, 0x81, 0x06, 0x62, 0x04, 0x82, 0x0e, 0x12, 0x0a];
+    for (quirks, expected) in [(Quirks::default(), [0x02, 0x08, 0]), (Quirks::cosmac_vip(), [0x40, 0x02, 1])] {
+        for backend in [Backend::Interpreter, Backend::Recompiler, Backend::Differential] {
+            let mut emu = Emulator::with_config(Config { backend, quirks, ..Config::default() }).unwrap();
+            emu.load(rom.clone()).unwrap();
+            emu.run_frames(1).unwrap();
+            assert_eq!([emu.v[1], emu.v[2], emu.v[15]], expected, "{:?} {:?}", quirks, backend);
+        }
+    }
+    let mut emu = Emulator::with_config(Config { backend: Backend::Recompiler, ..Config::cosmac_vip() }).unwrap();
+    emu.load(rom).unwrap();
+    assert_eq!(emu.block_at_pc().unwrap().instructions[2].op, MicroOp::ShiftRight { x: 1, y: 0 });
+}
+
+// Every write to /dev/full fails with "no space left on device".
+#[cfg(target_os = "linux")]
+#[test]
+fn trace_write_errors() {
+    let mut tracer = Tracer::new(0);
+    tracer.write_to("/dev/full").unwrap();
+    let mut emu = Emulator::new();
+    emu.set_tracer(tracer);
+    emu.load(vec![0x70, 0x01, 0x12, 0x00]).unwrap();
+    emu.run_frames(100).unwrap();
+    assert!(emu.flush_trace().is_err());
+    assert!(emu.flush_trace().is_ok());
+}
+
+#[test]
+fn sprite_panel_size() {
+    // Nothing in the trace history to go on.
+    let mut emu = Emulator::new();
+    emu.set_tracer(Tracer::new(0));
+    emu.load(vec![0xa0, 0x50, 0xd0, 0x05, 0x60, 0x01]).unwrap();
+    assert_eq!(emu.sprite_at_i().len(), 1 + 15);
+    for _ in 0..3 {
+        emu.step().unwrap();
+    }
+    assert_eq!(emu.sprite_at_i().len(), 1 + 5);
+}
+
+#[test]
+fn load_addresses() {
+    let mut emu = Emulator::with_config(Config::eti_660()).unwrap();
+    emu.load(vec![0x60, 0x42]).unwrap();
+    assert_eq!(emu.pc, 0x600);
+    assert_eq!(&emu.memory()[0x600..0x602], &[0x60, 0x42]);
+    emu.step().unwrap();
+    assert_eq!(emu.v[0], 0x42);
+
+    let mut emu = Emulator::with_config(Config { entry_point: 0x202, ..Config::default() }).unwrap();
+    emu.load(vec![0x60, 0x01, 0x60, 0x02]).unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.v[0], 0x02);
+}
+
+#[test]
+fn memory_images() {
+    let mut image = vec![0; 0x1000];
+    image[0x200..0x202].copy_from_slice(&[0x60, 0x42]);
+    image[0x50] = 0xaa;
+    let mut emu = Emulator::new();
+    emu.load_image(image).unwrap();
+    assert_eq!(emu.memory()[0x50], 0xaa);
+    emu.step().unwrap();
+    assert_eq!(emu.v[0], 0x42);
+
+    assert_eq!(Emulator::new().load_image(vec![0; 0x1001]), Err(Error::RomTooLarge { size: 0x1001, available: 0x1000 }));
+}
+
+#[test]
+fn roms_with_headers() {
+    let rom = Rom::with_header(b"C8HD\x03\x00\x03\x02\x60\x01\x60\x02").unwrap();
+    assert_eq!(rom, Rom { data: vec![0x60, 0x01, 0x60, 0x02], load_address: 0x300, entry_point: 0x302 });
+
+    let mut emu = Emulator::new();
+    emu.load_rom(&rom).unwrap();
+    emu.step().unwrap();
+    assert_eq!(emu.v[0], 0x02);
+
+    // An empty ROM loaded past the end of 4K memory.
+    let rom = Rom::with_header(b"C8HD\x20\x00\x20\x00").unwrap();
+    assert_eq!(Emulator::new().load_rom(&rom), Err(Error::OutOfBounds { addr: 0x2000 }));
+
+    assert_eq!(Rom::with_header(b"C8H"), Err(Error::InvalidHeader));
+    assert_eq!(Rom::with_header(&[0x60, 0x01, 0x60, 0x02, 0x00, 0xe0, 0x00, 0xe0]), Err(Error::InvalidHeader));
+}
+
+#[test]
+fn fonts() {
+    let emu = Emulator::with_config(Config::cosmac_vip()).unwrap();
+    assert_eq!(&emu.memory()[0x50..0xa0], Font::CosmacVip.small());
+    assert_eq!(&emu.memory()[0xa0..0x104], &SCHIP_BIG[..]);
+
+    let mut emu = Emulator::with_config(Config { font: Font::FishNChips, font_address: 0x100, ..Config::default() }).unwrap();
+    assert_eq!(&emu.memory()[0x100..0x150], Font::FishNChips.small());
+    emu.load(vec![0xf0, 0x29, 0xf0, 0x30]).unwrap();
+    emu.v[0] = 1;
+    emu.step().unwrap();
+    assert_eq!(emu.i, 0x105);
+    emu.step().unwrap();
+    assert_eq!(emu.i, 0x15a);
+
+    let mut emu = Emulator::new();
+    emu.load_font(&[0xff; 80]).unwrap();
+    assert_eq!(&emu.memory()[0x50..0xa0], &[0xff; 80]);
+    assert_eq!(emu.memory()[0xa0], SCHIP_BIG[0]);
+    assert_eq!(emu.load_font(&[0; 81]), Err(Error::InvalidFont { size: 81 }));
+}
+
+#[test]
+fn display_wait() {
+    // DRW, then ADD V0, 1 until the frame's budget runs out
+    let mut rom = vec![0xd0, 0x01];
+    for _ in 0..20 {
+        rom.extend_from_slice(&[0x70, 0x01]);
+    }
+
+    let mut emu = Emulator::new();
+    emu.load(rom.clone()).unwrap();
+    emu.delay_timer = 5;
+    emu.run_frame().unwrap();
+    assert_eq!(emu.v[0], emu.instructions_per_frame as u8 - 1);
+
+    let mut emu = Emulator::with_config(Config { quirks: Quirks { display_wait: true, ..Quirks::default() }, ..Config::default() }).unwrap();
+    emu.load(rom).unwrap();
+    emu.delay_timer = 5;
+    emu.run_frame().unwrap();
+    assert_eq!(emu.pc, 0x202);
+    assert_eq!(emu.delay_timer, 4);
+    emu.run_frame().unwrap();
+    assert_eq!(emu.v[0], emu.instructions_per_frame as u8);
+}
+
+#[test]
+fn resets() {
+    // LD V0, 42; LD I, 200; LD [I], V0 overwrites the first instruction;
+    // LD I, 300; LD [I], V0
+    let rom = vec![0x60, 0x2a, 0xa2, 0x00, 0xf0, 0x55, 0xa3, 0x00, 0xf0, 0x55];
+    let mut emu = Emulator::new();
+    emu.load_font(&[0xff; 80]).unwrap();
+    emu.load(rom.clone()).unwrap();
+    for _ in 0..5 {
+        emu.step().unwrap();
+    }
+    assert_eq!(emu.memory()[0x200], 42);
+
+    emu.reset();
+    assert_eq!((emu.pc, emu.i, emu.v[0]), (0x200, 0, 0));
+    assert_eq!(&emu.memory()[0x200..0x20a], &rom[..]);
+    assert_eq!(emu.memory()[0x300], 42);
+
+    emu.hard_reset();
+    assert_eq!(&emu.memory()[0x200..0x20a], &rom[..]);
+    assert_eq!(emu.memory()[0x300], 0);
+    assert_eq!(&emu.memory()[0x50..0xa0], &[0xff; 80]);
+    assert_eq!(&emu.memory()[0xa0..0x104], &SCHIP_BIG[..]);
+}
+
+#[test]
+fn profiler_after_reset() {
+    // LD V0, 3; CALL 206; JP 204; LD V0, 1; RET
+    let mut emu = Emulator::new();
+    emu.enable_profiler();
+    emu.load(vec![0x60, 0x03, 0x22, 0x06, 0x12, 0x04, 0x60, 0x01, 0x00, 0xee]).unwrap();
+    for _ in 0..3 {
+        emu.step().unwrap();
+    }
+    // Reset from inside the subroutine; what runs next is back in main.
+    emu.reset();
+    emu.step().unwrap();
+
+    let mut folded = Vec::new();
+    emu.profiler().unwrap().write_folded(&mut folded).unwrap();
+    assert_eq!(String::from_utf8(folded).unwrap(), "main 3\nmain;sub_206 1\n");
+}
+
+#[test]
+fn status_panel() {
+    let lines = status_view::render(&Status {
+        rom_name: String::from("pong.ch8"),
+        fps: 60.0,
+        speed: String::from("1x"),
+        instructions_per_frame: 11,
+        paused: false,
+        delay_timer: 0x3c,
+        sound_timer: 2,
+        pc: 0x200,
+        i: 0x300,
+        v: [0; 16],
+    });
+    assert_eq!(lines[0], "ROM   pong.ch8");
+    assert_eq!(lines[2], "DT 3C  ST 02  ♪ beep");
+}
+
+#[test]
+fn clickable_keypad() {
+    let mut keys_down = [false; 16];
+    keys_down[0xc] = true;
+    let lines = keypad_view::render(&keys_down);
+    assert_eq!(lines.len(), 13);
+    assert!(lines[1].starts_with("│   1   │   2   │   3   │"));
+    assert!(lines[1].ends_with(&format!("{}│", "   C   ".reverse())));
+    assert_eq!(lines[11], "│  (Z)  │  (X)  │  (C)  │  (V)  │");
+
+    let column = keypad_view::COLUMN;
+    let row = keypad_view::ROW;
+    assert_eq!(keypad_view::key_at(column + 1, row + 1), Some(0x1));
+    assert_eq!(keypad_view::key_at(column + 31, row + 11), Some(0xf));
+    assert_eq!(keypad_view::key_at(column + 8, row + 1), None);
+    assert_eq!(keypad_view::key_at(column + 1, row + 3), None);
+    assert_eq!(keypad_view::key_at(column + 33, row + 1), None);
+    assert_eq!(keypad_view::key_at(column - 1, row + 1), None);
+
+    let click = |kind, column, row| MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE };
+    let mut emu = Emulator::new();
+    emu.keypad.track_mouse(&click(MouseEventKind::Down(MouseButton::Left), column + 9, row + 5));
+    assert_eq!(emu.keypad.take_press(), Some(0x5));
+    assert!(emu.keypad.is_down(0x5));
+    emu.keypad.track_mouse(&click(MouseEventKind::Up(MouseButton::Left), column + 9, row + 5));
+    assert!(!emu.keypad.is_down(0x5));
+}
+
+#[test]
+fn frame_buffer() {
+    let frames = FrameBuffer::new();
+    assert!(frames.wait(Duration::ZERO).is_none());
+
+    let mut emu = Emulator::new();
+    frames.publish(emu.frame(None));
+    emu.display.pixels[0][0] = true;
+    frames.publish(emu.frame(Some(String::from("Halted"))));
+
+    // Only the newest frame is handed over, and only once.
+    let frame = frames.wait(Duration::ZERO).unwrap();
+    assert!(frame.pixels[0][0]);
+    assert_eq!(frame.halted.as_deref(), Some("Halted"));
+    assert!(frames.wait(Duration::ZERO).is_none());
+
+    std::thread::scope(|scope| {
+        scope.spawn(|| frames.publish(emu.frame(None)));
+        assert!(frames.wait(Duration::from_secs(5)).is_some());
+    });
+}
+
+#[test]
+fn status_follows_timers() {
+    // LD VA, 0A; LD DT, VA; LD V0, DT; JP 204 never draws anything.
+    let mut emu = Emulator::new();
+    emu.load(vec![0x6a, 0x0a, 0xfa, 0x15, 0xf0, 0x07, 0x12, 0x04]).unwrap();
+    let frames = FrameBuffer::new();
+    let (sender, input) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        let cpu = scope.spawn(|| emu.run_cpu(input, &frames));
+        let mut delay_timers = Vec::new();
+        while let Some(frame) = frames.wait(Duration::from_secs(5)) {
+            delay_timers.push(frame.status.delay_timer);
+            if frame.status.delay_timer == 0 && delay_timers.len() > 1 {
+                break;
+            }
+        }
+        sender.send(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))).unwrap();
+        assert_eq!(cpu.join().unwrap(), Ok(Halt::Quit));
+        assert_eq!(delay_timers.last(), Some(&0), "{:?}", delay_timers);
+    });
+}
+
+#[test]
+fn input_hangup() {
+    let mut emu = Emulator::new();
+    emu.load(vec![0x70, 0x01, 0x12, 0x00]).unwrap();
+    let (sender, input) = mpsc::channel::<Event>();
+    drop(sender);
+    assert_eq!(emu.run_cpu(input, &FrameBuffer::new()), Ok(Halt::Quit));
+}
+
+#[test]
+fn paused_keys() {
+    let press = |code| Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+    let frames = FrameBuffer::new();
+    let (sender, input) = mpsc::channel();
+
+    // Typing AB into the memory editor presses neither A nor B on the
+    // keypad, and F6 runs a frame with no key press pending.
+    let mut emu = Emulator::new();
+    emu.paused = true;
+    emu.memory_view.visible = true;
+    for code in [KeyCode::Char('a'), KeyCode::Char('b'), KeyCode::F(6)] {
+        sender.send(press(code)).unwrap();
+    }
+    assert_eq!(emu.wait_while_paused(&input, &frames), 1);
+    assert_eq!(emu.memory()[0x200], 0xab);
+    assert!(!emu.keypad.is_down(0x7));
+    assert_eq!(emu.keypad.take_press(), None);
+
+    // Without the debugger keys do reach the keypad, but a press made while
+    // paused is still dropped before the frame.
+    emu.memory_view.visible = false;
+    for code in [KeyCode::Char('1'), KeyCode::F(6)] {
+        sender.send(press(code)).unwrap();
+    }
+    assert_eq!(emu.wait_while_paused(&input, &frames), 1);
+    assert!(emu.keypad.is_down(0x1));
+    assert_eq!(emu.keypad.take_press(), None);
+}
@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+
+use crate::emulator::Opcode;
+
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub raw: u16,
+    pub opcode: Opcode,
+    pub v: [u8; 16],
+    pub i: u16,
+}
+
+impl TraceEntry {
+    fn format_line(&self) -> String {
+        let mut line = format!("{:08} {:04X}: {:04X}  {:<16}", self.cycle, self.pc, self.raw, self.opcode.to_string());
+        for (reg, val) in self.v.iter().enumerate() {
+            line.push_str(&format!(" V{:X}={:02X}", reg, val));
+        }
+        line.push_str(&format!(" I={:04X}", self.i));
+        line
+    }
+}
+
+// Keeps the last `capacity` executed instructions in memory and, when an
+// output file is set, writes every instruction passing the address and cycle
+// filters to it. The filters only apply to the file output.
+// A failed write stops the file output and is kept for `flush` to report, so
+// a full disk doesn't stop the emulator.
+pub struct Tracer {
+    output: Option<BufWriter<File>>,
+    error: Option<io::Error>,
+    addresses: Option<RangeInclusive<u16>>,
+    cycles: Option<RangeInclusive<u64>>,
+    history: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Tracer {
+        Tracer {
+            output: None,
+            error: None,
+            addresses: None,
+            cycles: None,
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn write_to(&mut self, filename: &str) -> std::io::Result<()> {
+        self.output = Some(BufWriter::new(File::create(filename)?));
+        Ok(())
+    }
+
+    pub fn set_address_filter(&mut self, addresses: RangeInclusive<u16>) {
+        self.addresses = Some(addresses);
+    }
+
+    pub fn set_cycle_filter(&mut self, cycles: RangeInclusive<u64>) {
+        self.cycles = Some(cycles);
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if let Some(output) = &mut self.output {
+            let in_addresses = self.addresses.as_ref().is_none_or(|r| r.contains(&entry.pc));
+            let in_cycles = self.cycles.as_ref().is_none_or(|r| r.contains(&entry.cycle));
+            if in_addresses && in_cycles {
+                if let Err(error) = writeln!(output, "{}", entry.format_line()) {
+                    self.error = Some(error);
+                    self.output = None;
+                }
+            }
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(entry);
+    }
+
+    pub fn last(&self) -> Option<&TraceEntry> {
+        self.history.back()
+    }
+
+    // Reports the first write that failed, if any.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        match &mut self.output {
+            Some(output) => output.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,18 +1,203 @@
-extern crate rand;
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::process;
+use std::time::Duration;
 
-use std::fs;
+use rustychips::emulator::Emulator;
+use rustychips::emulator::config::{Backend, Config, IndexIncrement, IndexOverflow, Quirks, UnknownOpcodePolicy};
+use rustychips::emulator::font::Font;
+use rustychips::emulator::rom::Rom;
+use rustychips::emulator::sprite_view;
+use rustychips::emulator::tracer::Tracer;
 
-mod emulator;
+fn main() {
+    if env::args().nth(1).as_deref() == Some("sprites") {
+        show_sprites(env::args().skip(2).collect());
+        return;
+    }
 
-use crate::emulator::Emulator;
+    let mut config = Config::default();
+    let mut rom = String::from("programs/chipquarium.ch8");
+    let mut trace_file = None;
+    let mut trace_addresses = None;
+    let mut trace_cycles = None;
+    let mut trace_buffer = 64;
+    let mut profile = None;
+    let mut instructions_per_frame = None;
+    let mut key_hold = None;
+    let mut format = String::from("plain");
+    let mut entry_point = None;
+    let mut font_file = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace_file = args.next(),
+            "--trace-addresses" => trace_addresses = args.next().map(|r| parse_range(&r, 16, u16::MAX as u64)),
+            "--trace-cycles" => trace_cycles = args.next().map(|r| parse_range(&r, 10, u64::MAX)),
+            "--trace-buffer" => trace_buffer = args.next()
+                .and_then(|n| n.parse().ok())
+                .expect("--trace-buffer needs a number of entries"),
+            "--profile" => profile = args.next(),
+            // Resets everything else, so it goes before other options.
+            "--platform" => config = match args.next().as_deref() {
+                Some("vip") => Config::cosmac_vip(),
+                Some("eti660") => Config::eti_660(),
+                Some("schip") => Config::schip(),
+                Some("modern") => Config::default(),
+                _ => panic!("--platform is one of vip, eti660, schip, modern")
+            },
+            "--load-address" => config.load_address = args.next()
+                .and_then(|a| u16::from_str_radix(&a, 16).ok())
+                .expect("--load-address needs a hex address"),
+            "--entry" => entry_point = args.next()
+                .and_then(|a| u16::from_str_radix(&a, 16).ok()),
+            "--format" => format = args.next().expect("--format is one of plain, image, header"),
+            "--quirks" => config.quirks = match args.next().as_deref() {
+                Some("vip") => Quirks::cosmac_vip(),
+                Some("schip") => Quirks::schip(),
+                Some("modern") => Quirks::default(),
+                _ => panic!("--quirks is one of vip, schip, modern")
+            },
+            "--font" => config.font = match args.next().as_deref() {
+                Some("modern") => Font::Modern,
+                Some("vip") => Font::CosmacVip,
+                Some("dream6800") => Font::Dream6800,
+                Some("eti660") => Font::Eti660,
+                Some("fish") => Font::FishNChips,
+                _ => panic!("--font is one of modern, vip, dream6800, eti660, fish")
+            },
+            "--font-file" => font_file = args.next(),
+            "--font-address" => config.font_address = args.next()
+                .and_then(|a| u16::from_str_radix(&a, 16).ok())
+                .expect("--font-address needs a hex address"),
+            "--index-increment" => config.quirks.index_increment = match args.next().as_deref() {
+                Some("none") => IndexIncrement::Unchanged,
+                Some("x") => IndexIncrement::ByX,
+                Some("x+1") => IndexIncrement::ByXPlusOne,
+                _ => panic!("--index-increment is one of none, x, x+1")
+            },
+            "--index-overflow" => config.index_overflow = match args.next().as_deref() {
+                Some("wrap") => IndexOverflow::Wrap,
+                Some("error") => IndexOverflow::Error,
+                _ => panic!("--index-overflow is one of wrap, error")
+            },
+            "--shift-vy" => config.quirks.shift_vy = true,
+            "--wrap-sprites" => config.quirks.wrap_sprites = true,
+            "--collision-rows" => config.quirks.collision_rows = true,
+            "--display-wait" => config.quirks.display_wait = true,
+            "--sys-noop" => config.sys_noop = true,
+            "--memory-size" => config.memory_size = args.next()
+                .and_then(|n| n.parse().ok())
+                .expect("--memory-size needs a number of bytes"),
+            "--protect-interpreter" => config.protect_interpreter = true,
+            "--stack-size" => config.stack_size = args.next()
+                .and_then(|n| n.parse().ok())
+                .expect("--stack-size needs a number of entries"),
+            "--unknown-opcode" => config.unknown_opcode = match args.next().as_deref() {
+                Some("halt") => UnknownOpcodePolicy::Halt,
+                Some("skip") => UnknownOpcodePolicy::Skip,
+                Some("trap") => UnknownOpcodePolicy::Trap,
+                _ => panic!("--unknown-opcode is one of halt, skip, trap")
+            },
+            "--backend" => config.backend = match args.next().as_deref() {
+                Some("interpreter") => Backend::Interpreter,
+                Some("recompiler") => Backend::Recompiler,
+                Some("differential") => Backend::Differential,
+                _ => panic!("--backend is one of interpreter, recompiler, differential")
+            },
+            "--ipf" => instructions_per_frame = args.next()
+                .map(|n| n.parse().expect("--ipf needs a number of instructions")),
+            "--key-hold" => key_hold = args.next()
+                .map(|ms| Duration::from_millis(ms.parse().expect("--key-hold needs a number of milliseconds"))),
+            _ => rom = arg
+        }
+    }
+
+    config.entry_point = entry_point.unwrap_or(config.load_address);
+    let mut emu = match Emulator::with_config(config) {
+        Ok(emu) => emu,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    if let Some(filename) = font_file {
+        emu.load_font(&load(&filename)).expect("Unable to load font");
+    }
+    let mut tracer = Tracer::new(trace_buffer);
+    if let Some(filename) = trace_file {
+        tracer.write_to(&filename).expect("Unable to create trace file");
+    }
+    if let Some(range) = trace_addresses {
+        tracer.set_address_filter(*range.start() as u16..=*range.end() as u16);
+    }
+    if let Some(range) = trace_cycles {
+        tracer.set_cycle_filter(range);
+    }
+    emu.set_tracer(tracer);
+    if let Some(instructions) = instructions_per_frame {
+        emu.set_instructions_per_frame(instructions);
+    }
+    if let Some(hold_time) = key_hold {
+        emu.set_key_hold_time(hold_time);
+    }
+    if profile.is_some() {
+        emu.enable_profiler();
+    }
+
+    let data = load(&rom);
+    let loaded = match format.as_str() {
+        "plain" => emu.load(data),
+        "image" => emu.load_image(data),
+        "header" => Rom::with_header(&data).and_then(|rom| emu.load_rom(&rom)),
+        _ => panic!("--format is one of plain, image, header")
+    };
+    loaded.expect("Unable to load ROM");
+    emu.set_rom_name(&Path::new(&rom).file_name().unwrap_or_default().to_string_lossy());
+    emu.set_save_path(&Path::new(&rom).with_extension("img").to_string_lossy());
+    if let Err(error) = emu.run() {
+        eprint!("{}\r\n", error);
+        for frame in emu.call_stack().iter().rev() {
+            eprint!("  in {:03X}, called from {:03X}, returns to {:03X}\r\n",
+                frame.target, frame.call_site, frame.return_address);
+        }
+    }
+    if let Err(error) = emu.flush_trace() {
+        eprintln!("Unable to write trace: {}", error);
+    }
+
+    if let (Some(prefix), Some(profiler)) = (profile, emu.profiler()) {
+        let mut report = BufWriter::new(File::create(format!("{}.txt", prefix)).expect("Unable to create profile report"));
+        profiler.write_report(&mut report).expect("Unable to write profile report");
+        let mut folded = BufWriter::new(File::create(format!("{}.folded", prefix)).expect("Unable to create folded stacks"));
+        profiler.write_folded(&mut folded).expect("Unable to write folded stacks");
+    }
+}
+
+// rustychips sprites <rom> [start] [end] [height]
+// Prints memory as a sprite sheet, addresses in hex. Defaults to the whole
+// program with 8x8 sprites.
+fn show_sprites(args: Vec<String>) {
+    let rom = args.first().expect("Usage: rustychips sprites <rom> [start] [end] [height]");
+    let hex_arg = |n: usize, default: usize| args.get(n)
+        .map(|a| usize::from_str_radix(a, 16).expect("Addresses are given in hex"))
+        .unwrap_or(default);
 
-fn main() {
     let mut emu = Emulator::new();
-//    let data = load("programs/ibm_logo.ch8");
-    let data = load("programs/chipquarium.ch8");
-    //println!("{:02x?}", data);
-    emu.load(data);
-    emu.run();
+    let data = load(rom);
+    let rom_end = 0x200 + data.len();
+    emu.load(data).expect("ROM does not fit in memory");
+
+    let start = hex_arg(1, 0x200);
+    let end = hex_arg(2, rom_end);
+    let height = args.get(3).map(|h| h.parse().expect("Height is a number of rows")).unwrap_or(8);
+    for line in sprite_view::render_sheet(emu.memory(), start, end, height, 8) {
+        println!("{}", line);
+    }
 }
 
 fn load(filename: &str) ->Vec<u8> {
@@ -20,3 +205,15 @@ fn load(filename: &str) ->Vec<u8> {
     println!("Data size = {}", data.len());
     data
 }
+
+// Parses "start-end" (inclusive) in the given radix, e.g. "200-2ff" or "0-5000".
+// Both ends must be at most `max`.
+fn parse_range(arg: &str, radix: u32, max: u64) -> RangeInclusive<u64> {
+    let (start, end) = arg.split_once('-').expect("Ranges are written as start-end");
+    let start = u64::from_str_radix(start, radix).expect("Invalid range start");
+    let end = u64::from_str_radix(end, radix).expect("Invalid range end");
+    if start > max || end > max {
+        panic!("Range {} goes past {}", arg, if radix == 16 { format!("{:X}", max) } else { max.to_string() });
+    }
+    start..=end
+}
@@ -0,0 +1,238 @@
+// Runs ROMs headless for a fixed number of frames, pressing keys from a
+// script, and compares a hash of the resulting framebuffer against a known
+// good value.
+//
+// The ROMs below are assembled inline so the harness always has coverage.
+// Tests for the Timendus CHIP-8 test suite
+// (https://github.com/Timendus/chip8-test-suite) are ignored until its ROMs
+// are copied to tests/roms; see tests/roms/README.md.
+// Every ROM also runs on the recompiler in differential mode, which stops
+// with an error if a block ever disagrees with the interpreter.
+
+use std::fs;
+use std::path::Path;
+
+use rustychips::emulator::Emulator;
+use rustychips::emulator::config::{Backend, Config};
+
+// FNV-1a over the framebuffer, one byte per pixel, row by row.
+fn framebuffer_hash(emu: &Emulator) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for row in emu.pixels() {
+        for pixel in row {
+            hash ^= *pixel as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+fn render(emu: &Emulator) -> String {
+    emu.pixels().iter()
+        .map(|row| row.iter().map(|p| if *p { '#' } else { '.' }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Keys are pressed and released as (frame, key, down) before the frame runs.
+fn run_rom(rom: Vec<u8>, config: Config, setup: impl Fn(&mut Emulator), keys: &[(u32, u8, bool)], frames: u32) -> Emulator {
+    let run = |backend| {
+        let mut emu = Emulator::with_config(Config { backend, ..config }).unwrap();
+        emu.load(rom.clone()).expect("ROM does not fit in memory");
+        setup(&mut emu);
+        for frame in 0..frames {
+            for &(_, key, down) in keys.iter().filter(|(at, _, _)| *at == frame) {
+                emu.set_key(key, down);
+            }
+            if emu.run_frame().expect("ROM stopped with an error").is_some() {
+                break;
+            }
+        }
+        emu
+    };
+    let emu = run(Backend::Interpreter);
+    let recompiled = run(Backend::Differential);
+    assert_eq!(emu.pixels(), recompiled.pixels(), "the recompiler drew something else\n{}", render(&recompiled));
+    emu
+}
+
+fn assert_framebuffer(name: &str, emu: &Emulator, expected: Option<u64>) {
+    let hash = framebuffer_hash(emu);
+    match expected {
+        Some(expected) => assert_eq!(hash, expected,
+            "{}: framebuffer hash {:#018x} does not match {:#018x}\n{}", name, hash, expected, render(emu)),
+        None => panic!("{}: no hash recorded yet, check the screen and record {:#018x}\n{}",
+            name, hash, render(emu)),
+    }
+}
+
+// Timendus ROMs read 0x1FF to pick a platform or test without showing their
+// menu. The ROMs aren't in the repository, so the tests that use them are
+// ignored and fail outright if run without them.
+fn run_suite_rom(file: &str, config: Config, platform: Option<u8>, keys: &[(u32, u8, bool)], frames: u32) -> Emulator {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(file);
+    let rom = fs::read(&path)
+        .unwrap_or_else(|error| panic!("{}: {}, see tests/roms/README.md", path.display(), error));
+    run_rom(rom, config, |emu| if let Some(platform) = platform {
+        emu.write_memory(0x1ff, platform).unwrap();
+    }, keys, frames)
+}
+
+// Code is placed at 0x200 and data at 0x300.
+fn assemble(code: &[u16], data: &[u8]) -> Vec<u8> {
+    let mut rom: Vec<u8> = code.iter().flat_map(|word| word.to_be_bytes()).collect();
+    assert!(rom.len() <= 0x100, "code overlaps data");
+    rom.resize(0x100, 0);
+    rom.extend_from_slice(data);
+    rom
+}
+
+fn jump_to_self(code: &[u16]) -> u16 {
+    0x1000 | (0x200 + 2 * code.len() as u16)
+}
+
+// Draws a two pixel mark at (VA, VB) if Vx equals the value, then moves VA
+// along. Expects I to point at the mark.
+fn expect_reg(reg: u16, value: u8) -> [u16; 3] {
+    [0x4000 | reg << 8 | value as u16, 0xdab1, 0x7a02]
+}
+
+fn checks_rom(checks: &[&[u16]]) -> Vec<u8> {
+    let mut code = vec![
+        0x00e0, // CLS
+        0xa300, // LD I, 300
+        0x6a00, // LD VA, 0
+        0x6b00, // LD VB, 0
+    ];
+    for check in checks {
+        code.extend_from_slice(check);
+    }
+    code.push(jump_to_self(&code));
+    assemble(&code, &[0xc0])
+}
+
+#[test]
+fn draws_sprites_and_reports_collisions() {
+    let mut code = vec![
+        0x00e0, // CLS
+        0xa300, // LD I, 300
+        0x6000, // LD V0, 0
+        0x6100, // LD V1, 0
+        0xd015, // DRW V0, V1, 5
+        0x600a, // LD V0, 10
+        0x610c, // LD V1, 12
+        0xd015, // DRW V0, V1, 5
+        0x6202, // LD V2, 2
+        0x8024, // ADD V0, V2
+        0xd015, // DRW V0, V1, 5 (overlaps, sets VF)
+        0x8cf0, // LD VC, VF
+        0x6014, // LD V0, 20
+        0x6100, // LD V1, 0
+        0xa305, // LD I, 305
+        0xd011, // DRW V0, V1, 1
+        0x3c01, // SE VC, 1
+        0x00e0, // CLS (skipped)
+    ];
+    code.push(jump_to_self(&code));
+    let rom = assemble(&code, &[0xf0, 0x90, 0xf0, 0x90, 0xf0, 0xff]);
+
+    let emu = run_rom(rom, Config::default(), |_| {}, &[], 10);
+    assert_framebuffer("sprites", &emu, Some(0xe4c2015e658a4639));
+}
+
+#[test]
+fn register_operations() {
+    let rom = checks_rom(&[
+        &[0x6012], &expect_reg(0, 0x12),                    // LD
+        &[0x7001], &expect_reg(0, 0x13),                    // ADD byte
+        &[0x70ff], &expect_reg(0, 0x12),                    // ADD byte wraps
+        &[0x6133, 0x8010], &expect_reg(0, 0x33),            // LD reg
+        &[0x60f0, 0x610f, 0x8011], &expect_reg(0, 0xff),    // OR
+        &[0x60f0, 0x613c, 0x8012], &expect_reg(0, 0x30),    // AND
+        &[0x613c, 0x8013], &expect_reg(0, 0x0c),            // XOR
+        &[0x6005, 0x6103, 0x8014], &expect_reg(0, 0x08),    // ADD reg
+        // Drawing the marks changes VF, so the flag is copied to VC first.
+        &[0x8015, 0x8cf0], &expect_reg(0, 0x05), &expect_reg(0xc, 1),  // SUB
+        &[0x6003, 0x6105, 0x8015, 0x8cf0], &expect_reg(0, 0xfe), &expect_reg(0xc, 0),
+        &[0x6002, 0x6107, 0x8017, 0x8cf0], &expect_reg(0, 0x05), &expect_reg(0xc, 1),  // SUBN
+        &[0x6081, 0x8006, 0x8cf0], &expect_reg(0, 0x40), &expect_reg(0xc, 1),  // SHR
+        &[0x6081, 0x800e, 0x8cf0], &expect_reg(0, 0x02), &expect_reg(0xc, 1),  // SHL
+    ]);
+
+    let emu = run_rom(rom, Config::default(), |_| {}, &[], 30);
+    assert_framebuffer("register operations", &emu, Some(0xee711ac1b783f5a9));
+}
+
+#[test]
+fn memory_operations() {
+    let rom = checks_rom(&[
+        // LD B, V0 then LD V2, [I]
+        &[0x607b, 0xa310, 0xf033, 0xf265, 0xa300], &expect_reg(0, 1), &expect_reg(1, 2), &expect_reg(2, 3),
+        // LD [I], V2 then read back into other registers
+        &[0x60aa, 0x61bb, 0x62cc, 0xa320, 0xf255, 0x6000, 0x6100, 0x6200, 0xf265, 0xa300],
+        &expect_reg(0, 0xaa), &expect_reg(1, 0xbb), &expect_reg(2, 0xcc),
+    ]);
+
+    let emu = run_rom(rom, Config::default(), |_| {}, &[], 30);
+    assert_framebuffer("memory operations", &emu, Some(0x6a58ab26d1dcc391));
+}
+
+#[test]
+fn scripted_keys() {
+    let rom = checks_rom(&[
+        &[0xf00a], &expect_reg(0, 0xb),                     // LD V0, K
+        &[0x610b, 0xe1a1, 0xdab1, 0x7a02],                  // SKNP V1 while B is held
+        &[0xf00a], &expect_reg(0, 0x5),                     // LD V0, K gets the next key
+    ]);
+
+    let emu = run_rom(rom, Config::default(), |_| {}, &[(5, 0xb, true), (10, 0xb, false), (15, 0x5, true)], 20);
+    assert_framebuffer("scripted keys", &emu, Some(0xe53011abdfd59e63));
+}
+
+#[test]
+#[ignore = "needs 3-corax+.ch8 in tests/roms"]
+fn corax_plus() {
+    let emu = run_suite_rom("3-corax+.ch8", Config::default(), None, &[], 60);
+    assert_framebuffer("3-corax+.ch8", &emu, None);
+}
+
+#[test]
+#[ignore = "needs 4-flags.ch8 in tests/roms"]
+fn flags() {
+    let emu = run_suite_rom("4-flags.ch8", Config::default(), None, &[], 120);
+    assert_framebuffer("4-flags.ch8", &emu, None);
+}
+
+// Platform 1 is the original CHIP-8, so it runs with the VIP's quirks.
+#[test]
+#[ignore = "needs 5-quirks.ch8 in tests/roms"]
+fn quirks() {
+    let emu = run_suite_rom("5-quirks.ch8", Config::cosmac_vip(), Some(1), &[], 600);
+    assert_framebuffer("5-quirks.ch8", &emu, None);
+}
+
+// Test 1 is Ex9E, which lights up the keys that are down. Key 5 is still
+// held at the end.
+#[test]
+#[ignore = "needs 6-keypad.ch8 in tests/roms"]
+fn keypad_down() {
+    let emu = run_suite_rom("6-keypad.ch8", Config::default(), Some(1), &[(30, 0x5, true)], 60);
+    assert_framebuffer("6-keypad.ch8", &emu, None);
+}
+
+// Test 3 is Fx0A, which shows the key it got once the key is released.
+#[test]
+#[ignore = "needs 6-keypad.ch8 in tests/roms"]
+fn keypad_wait() {
+    let emu = run_suite_rom("6-keypad.ch8", Config::default(), Some(3), &[(30, 0x5, true), (40, 0x5, false)], 60);
+    assert_framebuffer("6-keypad.ch8", &emu, None);
+}
+
+// Holding B beeps for as long as it is down.
+#[test]
+#[ignore = "needs 7-beep.ch8 in tests/roms"]
+fn beep() {
+    let emu = run_suite_rom("7-beep.ch8", Config::default(), None, &[(30, 0xb, true)], 60);
+    assert!(emu.sound_timer() > 0, "not beeping with B held");
+    assert_framebuffer("7-beep.ch8", &emu, None);
+}